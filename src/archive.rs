@@ -0,0 +1,59 @@
+use crate::{
+    converter::{FileReport, FileSummary},
+    fs_util::{create_output_file, read_file_contents, relative_path},
+    manifest::{Manifest, MANIFEST_FILENAME},
+};
+use eyre::{Result, WrapErr};
+use std::{io::Write, path::Path};
+use zip::{write::FileOptions, CompressionMethod, DateTime, ZipWriter};
+
+// -------------------------------------------------------------------------------------------------
+
+/// Writes the outputs of all successfully converted files and a manifest into the ZIP archive
+/// `filename`. The entries are sorted by name and have a fixed timestamp and permissions, so the
+/// same outputs always result in the same archive.
+pub(crate) fn write_output_archive(
+    filename: &Path,
+    output_root: &Path,
+    mut manifest: Manifest,
+    reports: &[FileReport],
+) -> Result<()> {
+    let mut entries: Vec<(String, Vec<u8>)> = Vec::new();
+
+    for report in reports {
+        if let Ok(FileSummary::Image { .. } | FileSummary::Text { .. } | FileSummary::UpToDate) =
+            report.result
+        {
+            entries.push((
+                relative_path(&report.output_path, output_root),
+                read_file_contents(&report.output_path)?,
+            ));
+        }
+    }
+
+    manifest.make_reproducible();
+    entries.push((
+        MANIFEST_FILENAME.to_owned(),
+        manifest.to_json()?.into_bytes(),
+    ));
+
+    entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+    entries.dedup_by(|(a, _), (b, _)| a == b);
+
+    let options = FileOptions::default()
+        .compression_method(CompressionMethod::Deflated)
+        .last_modified_time(DateTime::default())
+        .unix_permissions(0o644);
+
+    let write_archive = || -> Result<()> {
+        let mut zip = ZipWriter::new(create_output_file(filename)?);
+        for (name, contents) in &entries {
+            zip.start_file(name.as_str(), options)?;
+            zip.write_all(contents)?;
+        }
+        zip.finish()?;
+        Ok(())
+    };
+
+    write_archive().wrap_err_with(|| format!("Unable to write to '{}'.", filename.display()))
+}
@@ -0,0 +1,279 @@
+use crate::fs_util::read_file_contents;
+use eyre::{bail, eyre, Result, WrapErr};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, convert::TryFrom, fmt, path::Path, sync::OnceLock};
+
+// -------------------------------------------------------------------------------------------------
+
+const GERMAN_TABLE: &str = include_str!("../tables/german.txt");
+
+/// The mapping between the bytes of TCT files and characters, loaded from a table file. See
+/// `tables/german.txt` for the format.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CodecTable {
+    name: String,
+    chars: [Option<char>; 256],
+    bytes: BTreeMap<char, u8>,
+}
+
+fn parse_byte(s: &str) -> Option<u8> {
+    match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(hex) => u8::from_str_radix(hex, 16).ok(),
+        None => s.parse().ok(),
+    }
+}
+
+fn parse_char(s: &str) -> Option<char> {
+    match s.strip_prefix("U+").or_else(|| s.strip_prefix("u+")) {
+        Some(hex) => char::from_u32(u32::from_str_radix(hex, 16).ok()?),
+        None => {
+            let mut chars = s.chars();
+            chars.next().filter(|_| chars.next().is_none())
+        }
+    }
+}
+
+impl CodecTable {
+    /// The table of the German release of the game, used by default.
+    pub fn german() -> &'static Self {
+        static GERMAN: OnceLock<CodecTable> = OnceLock::new();
+        GERMAN.get_or_init(|| {
+            let mut table = Self::parse(GERMAN_TABLE).expect("The German codec table is invalid.");
+            table.name = "german".to_owned();
+            table
+        })
+    }
+
+    /// Parses the contents of a table file, the table is named `custom`.
+    pub fn parse(s: &str) -> Result<Self> {
+        let mut table = Self {
+            name: "custom".to_owned(),
+            chars: [None; 256],
+            bytes: BTreeMap::new(),
+        };
+
+        for (line_index, line) in s.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let invalid_entry = || eyre!("Invalid entry '{}' at line {}.", line, line_index + 1);
+
+            let mut fields = line.split_whitespace();
+            let (Some(bytes), Some(first_char), None) =
+                (fields.next(), fields.next(), fields.next())
+            else {
+                return Err(invalid_entry());
+            };
+
+            let (first_byte, last_byte) = match bytes.split_once('-') {
+                Some((first, last)) => (parse_byte(first), parse_byte(last)),
+                None => (parse_byte(bytes), parse_byte(bytes)),
+            };
+            let (Some(first_byte), Some(last_byte), Some(first_char)) =
+                (first_byte, last_byte, parse_char(first_char))
+            else {
+                return Err(invalid_entry());
+            };
+            if first_byte > last_byte {
+                return Err(invalid_entry());
+            }
+
+            for byte in first_byte..=last_byte {
+                let c = char::from_u32(first_char as u32 + (byte - first_byte) as u32)
+                    .ok_or_else(invalid_entry)?;
+                if table.chars[byte as usize].is_some() {
+                    bail!("Byte {} is mapped twice at line {}.", byte, line_index + 1);
+                }
+                table.chars[byte as usize] = Some(c);
+                table.bytes.entry(c).or_insert(byte);
+            }
+        }
+
+        Ok(table)
+    }
+
+    /// Loads a table file, the table is named like the file without extension.
+    pub fn load(filename: &Path) -> Result<Self> {
+        let contents = read_file_contents(filename)?;
+        let mut table = std::str::from_utf8(&contents)
+            .map_err(|err| eyre!(err))
+            .and_then(Self::parse)
+            .wrap_err_with(|| format!("Unable to read codec table '{}'.", filename.display()))?;
+
+        if let Some(name) = filename.file_stem() {
+            table.name = name.to_string_lossy().into_owned();
+        }
+        Ok(table)
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the table with all bytes moved by `shift`, mappings moved out of the byte range
+    /// are dropped.
+    pub fn shifted(&self, shift: i16) -> Self {
+        let mut table = Self {
+            name: self.name.clone(),
+            chars: [None; 256],
+            bytes: BTreeMap::new(),
+        };
+
+        for (byte, c) in self.chars.iter().enumerate() {
+            let (Some(c), Ok(shifted)) = (c, u8::try_from(byte as i16 + shift)) else {
+                continue;
+            };
+            table.chars[shifted as usize] = Some(*c);
+            if self.encode_char(*c) == Some(byte as u8) {
+                table.bytes.insert(*c, shifted);
+            }
+        }
+
+        table
+    }
+
+    /// Returns the character of `byte`, if any.
+    pub fn decode_byte(&self, byte: u8) -> Option<char> {
+        self.chars[byte as usize]
+    }
+
+    /// Returns the byte of `c`, if any.
+    pub fn encode_char(&self, c: char) -> Option<u8> {
+        self.bytes.get(&c).copied()
+    }
+}
+
+impl Default for CodecTable {
+    fn default() -> Self {
+        Self::german().clone()
+    }
+}
+
+// -------------------------------------------------------------------------------------------------
+
+/// A variant of the text encoding of the game, chosen by [`detect_encoding`].
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TextEncoding {
+    /// The name of the codec table.
+    pub table: String,
+    /// The offset the bytes of the table were moved by.
+    pub shift: i16,
+}
+
+impl fmt::Display for TextEncoding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.shift == 0 {
+            write!(f, "'{}'", self.table)
+        } else {
+            write!(f, "'{}' shifted by {:+}", self.table, self.shift)
+        }
+    }
+}
+
+/// The shifts tried for every table, releases are not expected to move the bytes any further.
+const MAX_SHIFT: i16 = 16;
+
+/// The relative frequencies of the letters in German texts in percent.
+const LETTER_FREQUENCIES: &[(char, f64)] = &[
+    ('e', 16.4),
+    ('n', 9.8),
+    ('i', 7.6),
+    ('s', 7.3),
+    ('r', 7.0),
+    ('a', 6.5),
+    ('t', 6.2),
+    ('d', 5.1),
+    ('h', 4.8),
+    ('u', 4.2),
+    ('l', 3.4),
+    ('c', 3.1),
+    ('g', 3.0),
+    ('m', 2.5),
+    ('o', 2.5),
+    ('b', 1.9),
+    ('w', 1.9),
+    ('f', 1.7),
+    ('k', 1.2),
+    ('z', 1.1),
+    ('p', 0.8),
+    ('v', 0.8),
+    ('j', 0.3),
+    ('y', 0.04),
+    ('x', 0.03),
+    ('q', 0.02),
+];
+
+/// Rates how plausible `data` decoded with `table` is as German text, higher is better.
+fn plausibility(data: &[u8], table: &CodecTable) -> f64 {
+    let chars: Vec<Option<char>> = data.iter().map(|&byte| table.decode_byte(byte)).collect();
+    let is_letter = |i: Option<usize>| {
+        i.and_then(|i| chars.get(i).copied().flatten())
+            .is_some_and(char::is_alphabetic)
+    };
+
+    let mut score = 0.0;
+    for (i, c) in chars.iter().enumerate() {
+        let previous_is_letter = is_letter(i.checked_sub(1));
+        let next_is_letter = is_letter(Some(i + 1));
+
+        score += match *c {
+            None => -10.0,
+            Some('\n') => 0.0,
+            Some(c) if c.is_control() => -10.0,
+            Some(' ') => 2.0,
+            Some(c) if c.is_ascii_lowercase() => LETTER_FREQUENCIES
+                .iter()
+                .find(|&&(letter, _)| letter == c)
+                .map_or(0.0, |&(_, frequency)| frequency / 4.0),
+            Some(c) if c.is_ascii_uppercase() => {
+                if previous_is_letter {
+                    -0.5
+                } else {
+                    0.5
+                }
+            }
+            // Umlauts and ß are only plausible within words.
+            Some('ä' | 'ö' | 'ü' | 'ß') if previous_is_letter => 2.0,
+            Some('Ä' | 'Ö' | 'Ü') if next_is_letter && !previous_is_letter => 2.0,
+            Some('ä' | 'ö' | 'ü' | 'ß' | 'Ä' | 'Ö' | 'Ü') => -3.0,
+            Some(c) if c.is_ascii() => 0.0,
+            Some(_) => -1.0,
+        };
+    }
+
+    score / data.len().max(1) as f64
+}
+
+/// Detects which of the `tables`, with its bytes shifted by up to 16 in either direction, the
+/// text `data` is encoded with. Ties are resolved in favor of the earlier tables and smaller
+/// shifts.
+pub fn detect_encoding(data: &[u8], tables: &[&CodecTable]) -> (TextEncoding, CodecTable) {
+    let shifts: Vec<i16> = std::iter::once(0)
+        .chain((1..=MAX_SHIFT).flat_map(|shift| vec![shift, -shift]))
+        .collect();
+
+    let mut best: Option<(f64, i16, CodecTable)> = None;
+    for table in tables {
+        for &shift in &shifts {
+            let candidate = table.shifted(shift);
+            let score = plausibility(data, &candidate);
+            if best
+                .as_ref()
+                .is_none_or(|(best_score, ..)| score > *best_score)
+            {
+                best = Some((score, shift, candidate));
+            }
+        }
+    }
+
+    let (_, shift, table) = best.unwrap_or_else(|| (0.0, 0, CodecTable::default()));
+    (
+        TextEncoding {
+            table: table.name.clone(),
+            shift,
+        },
+        table,
+    )
+}
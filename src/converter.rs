@@ -0,0 +1,848 @@
+use crate::{
+    archive::write_output_archive,
+    codec::{CodecTable, TextEncoding},
+    fs_util::{find_dir_ignoring_case, sha256_hex, to_output_filename},
+    gfx::{convert_pcx, convert_png, GraphicsOptions, TransparencyOutput, TransparentColor},
+    manifest::{Manifest, MANIFEST_FILENAME},
+    palette::{PaletteDepth, PaletteMode},
+    pcx::DecodeMode,
+    source::{open_game_source, InputSource},
+    state::{options_fingerprint, state_filename, ConversionState, StateEntry},
+    text::{convert_tct, convert_txt, LineEnding, TextFileEncoding, TextOptions, UnmappedBytes},
+};
+use eyre::{bail, Result, WrapErr};
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt,
+    fs::{read_dir, remove_file, DirBuilder},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+// -------------------------------------------------------------------------------------------------
+
+const GFX_INPUT_DIR: &str = "GRAFIK";
+const GFX_OUTPUT_DIR: &str = "GRAFIK_PNG";
+
+const TEXT_INPUT_DIR: &str = "TEXT";
+const TEXT_OUTPUT_DIR: &str = "TEXT_TXT";
+
+// -------------------------------------------------------------------------------------------------
+
+/// The names of the resource directories. Existing directories are looked up ignoring case, e.g.
+/// `grafik` is found for `GRAFIK`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DirectoryLayout {
+    /// The PCX images of the game, `GRAFIK` by default.
+    pub graphics_input: String,
+    /// The converted PNG images, `GRAFIK_PNG` by default.
+    pub graphics_output: String,
+    /// The TCT texts of the game, `TEXT` by default.
+    pub text_input: String,
+    /// The converted text files, `TEXT_TXT` by default.
+    pub text_output: String,
+}
+
+impl Default for DirectoryLayout {
+    fn default() -> Self {
+        Self {
+            graphics_input: GFX_INPUT_DIR.to_owned(),
+            graphics_output: GFX_OUTPUT_DIR.to_owned(),
+            text_input: TEXT_INPUT_DIR.to_owned(),
+            text_output: TEXT_OUTPUT_DIR.to_owned(),
+        }
+    }
+}
+
+// -------------------------------------------------------------------------------------------------
+
+/// The kinds of game resources the converter knows how to handle.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ResourceKind {
+    /// The PCX images in `GRAFIK`, converted to PNG files in `GRAFIK_PNG`.
+    Graphics,
+    /// The TCT texts in `TEXT`, converted to UTF-8 text files in `TEXT_TXT`.
+    Texts,
+}
+
+impl ResourceKind {
+    pub const ALL: [ResourceKind; 2] = [ResourceKind::Graphics, ResourceKind::Texts];
+}
+
+impl fmt::Display for ResourceKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ResourceKind::Graphics => f.write_str("graphics"),
+            ResourceKind::Texts => f.write_str("texts"),
+        }
+    }
+}
+
+/// Whether the game's resources are converted to regular files or back again.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Default, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    /// Converts the game's resources to PNG and text files.
+    #[default]
+    Export,
+    /// Converts edited PNG and text files back to the game's formats, e.g. `TEXT_TXT/*.TXT` to
+    /// `TEXT/*.TCT`. The original PCX files have to be present in the output directory, their
+    /// obfuscated header bytes are carried over.
+    Import,
+}
+
+/// What was done for a single file.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FileSummary {
+    Image {
+        width: u32,
+        height: u32,
+        /// The depth of the color values of the original palette, `None` for true color images
+        /// and imported images.
+        palette_depth: Option<PaletteDepth>,
+        /// The SHA-256 digest of the written palette, `None` for true color images.
+        palette_hash: Option<String>,
+    },
+    Text {
+        characters: usize,
+        /// The detected variant of the text encoding, `None` unless the detection is enabled.
+        encoding: Option<TextEncoding>,
+    },
+    /// The output of an earlier incremental conversion is up to date and was kept.
+    UpToDate,
+    /// The output of an earlier incremental conversion was deleted because its input no longer
+    /// exists.
+    Removed,
+}
+
+/// The result of converting a single input file.
+#[derive(Debug)]
+pub struct FileReport {
+    pub kind: ResourceKind,
+    pub input_path: PathBuf,
+    pub output_path: PathBuf,
+    pub result: Result<FileSummary>,
+    /// Problems that were worked around during the conversion.
+    pub warnings: Vec<String>,
+    /// The SHA-256 digest of the input file, `None` if it could not be read.
+    pub(crate) input_hash: Option<String>,
+    /// The summary of the conversion that wrote an up to date output, if it is known.
+    pub(crate) previous_summary: Option<FileSummary>,
+}
+
+/// The result of converting one installation found by [`Converter::convert_batch`].
+#[derive(Debug)]
+pub struct BatchReport {
+    pub installation: PathBuf,
+    pub result: Result<Vec<FileReport>>,
+}
+
+/// The number of files per outcome of a conversion.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ConversionSummary {
+    pub converted: usize,
+    pub up_to_date: usize,
+    pub removed: usize,
+    pub failed: usize,
+}
+
+impl ConversionSummary {
+    pub fn from_reports(reports: &[FileReport]) -> Self {
+        let mut summary = Self::default();
+        for report in reports {
+            match report.result {
+                Ok(FileSummary::Image { .. } | FileSummary::Text { .. }) => summary.converted += 1,
+                Ok(FileSummary::UpToDate) => summary.up_to_date += 1,
+                Ok(FileSummary::Removed) => summary.removed += 1,
+                Err(_) => summary.failed += 1,
+            }
+        }
+        summary
+    }
+
+    pub fn add(&mut self, other: &ConversionSummary) {
+        self.converted += other.converted;
+        self.up_to_date += other.up_to_date;
+        self.removed += other.removed;
+        self.failed += other.failed;
+    }
+}
+
+impl fmt::Display for ConversionSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} converted, {} failed", self.converted, self.failed)?;
+        if self.up_to_date > 0 {
+            write!(f, ", {} up to date", self.up_to_date)?;
+        }
+        if self.removed > 0 {
+            write!(f, ", {} removed", self.removed)?;
+        }
+        Ok(())
+    }
+}
+
+/// The error returned when some files or installations could not be converted, the other ones
+/// were converted nevertheless.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConversionFailed {
+    /// The input files or installations that failed.
+    pub failed: Vec<PathBuf>,
+    /// The number of files or installations that were attempted.
+    pub total: usize,
+}
+
+impl ConversionFailed {
+    /// Collects the failed files of `reports`, `None` if all files were converted.
+    pub fn from_reports(reports: &[FileReport]) -> Option<Self> {
+        let failed: Vec<PathBuf> = reports
+            .iter()
+            .filter(|report| report.result.is_err())
+            .map(|report| report.input_path.clone())
+            .collect();
+
+        if failed.is_empty() {
+            None
+        } else {
+            Some(Self {
+                failed,
+                total: reports.len(),
+            })
+        }
+    }
+}
+
+impl fmt::Display for ConversionFailed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} of {} files could not be converted:",
+            self.failed.len(),
+            self.total
+        )?;
+        for filename in &self.failed {
+            write!(f, "\n    '{}'", filename.display())?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ConversionFailed {}
+
+/// Converts the resources of a 'Jonathan' installation.
+///
+/// ```no_run
+/// use jonathan_converter::{Converter, ResourceKind};
+///
+/// let reports = Converter::new("/games/jonathan")
+///     .output_root("/tmp/jonathan")
+///     .resource_kinds(&[ResourceKind::Graphics])
+///     .convert()?;
+/// # Ok::<(), eyre::Report>(())
+/// ```
+#[derive(Clone, Debug)]
+pub struct Converter {
+    pub(crate) input_root: PathBuf,
+    output_root: Option<PathBuf>,
+    pub(crate) resource_kinds: Vec<ResourceKind>,
+    direction: Direction,
+    layout: DirectoryLayout,
+    graphics_options: GraphicsOptions,
+    text_options: TextOptions,
+    write_manifest: bool,
+    output_archive: Option<PathBuf>,
+    incremental: bool,
+}
+
+impl Converter {
+    /// Creates a converter for the game installed in `input_root`. By default all resource kinds
+    /// are converted and the output is written into `input_root`.
+    pub fn new(input_root: impl Into<PathBuf>) -> Self {
+        Self {
+            input_root: input_root.into(),
+            output_root: None,
+            resource_kinds: ResourceKind::ALL.to_vec(),
+            direction: Direction::Export,
+            layout: DirectoryLayout::default(),
+            graphics_options: GraphicsOptions::default(),
+            text_options: TextOptions::default(),
+            write_manifest: false,
+            output_archive: None,
+            incremental: false,
+        }
+    }
+
+    /// Writes the output directories into `output_root` instead of the game directory.
+    pub fn output_root(mut self, output_root: impl Into<PathBuf>) -> Self {
+        self.output_root = Some(output_root.into());
+        self
+    }
+
+    /// Overrides the names of the resource directories.
+    pub fn directory_layout(mut self, layout: DirectoryLayout) -> Self {
+        self.layout = layout;
+        self
+    }
+
+    /// Restricts the conversion to the given resource kinds.
+    pub fn resource_kinds(mut self, resource_kinds: &[ResourceKind]) -> Self {
+        self.resource_kinds = resource_kinds.to_vec();
+        self
+    }
+
+    /// Sets the direction of the conversion, [`Direction::Export`] by default.
+    pub fn direction(mut self, direction: Direction) -> Self {
+        self.direction = direction;
+        self
+    }
+
+    /// Sets how damaged PCX files are decoded, [`DecodeMode::Strict`] by default.
+    pub fn decode_mode(mut self, decode_mode: DecodeMode) -> Self {
+        self.graphics_options.decode_mode = decode_mode;
+        self
+    }
+
+    /// Sets the palette index that is transparent in the converted PNG files,
+    /// [`TransparentColor::None`] by default.
+    pub fn transparent_color(mut self, transparent_color: TransparentColor) -> Self {
+        self.graphics_options.transparent_color = transparent_color;
+        self
+    }
+
+    /// Sets how transparency is stored in the converted PNG files, [`TransparencyOutput::Trns`]
+    /// by default.
+    pub fn transparency_output(mut self, transparency_output: TransparencyOutput) -> Self {
+        self.graphics_options.transparency_output = transparency_output;
+        self
+    }
+
+    /// Sets how the color values of the PCX palettes are interpreted, [`PaletteMode::Auto`] by
+    /// default.
+    pub fn palette_mode(mut self, palette_mode: PaletteMode) -> Self {
+        self.graphics_options.palette_mode = palette_mode;
+        self
+    }
+
+    /// Sets how bytes of TCT files without a character are handled, [`UnmappedBytes::Reject`] by
+    /// default. The same mode has to be used to import the text files again.
+    pub fn unmapped_bytes(mut self, unmapped_bytes: UnmappedBytes) -> Self {
+        self.text_options.unmapped_bytes = unmapped_bytes;
+        self
+    }
+
+    /// Sets the mapping between the bytes of TCT files and characters,
+    /// [`CodecTable::german`] by default. The same table has to be used to import the text files
+    /// again.
+    pub fn codec_table(mut self, codec_table: CodecTable) -> Self {
+        self.text_options.codec_table = Arc::new(codec_table);
+        self
+    }
+
+    /// Detects the codec table and the shift of its bytes for every TCT file instead of using the
+    /// codec table as it is, disabled by default. The codec table and the German table are
+    /// tried.
+    pub fn detect_encoding(mut self, detect_encoding: bool) -> Self {
+        self.text_options.detect_encoding = detect_encoding;
+        self
+    }
+
+    /// Sets the line ending of the written text files, the one of the platform the converter was
+    /// built for by default.
+    pub fn line_ending(mut self, line_ending: LineEnding) -> Self {
+        self.text_options.line_ending = line_ending;
+        self
+    }
+
+    /// Sets whether the written UTF-8 and UTF-16 text files start with a byte order mark, enabled
+    /// by default.
+    pub fn byte_order_mark(mut self, byte_order_mark: bool) -> Self {
+        self.text_options.byte_order_mark = byte_order_mark;
+        self
+    }
+
+    /// Sets the character encoding of the text files, [`TextFileEncoding::Utf8`] by default. Text
+    /// files with a byte order mark are always read in the encoding it indicates.
+    pub fn text_file_encoding(mut self, file_encoding: TextFileEncoding) -> Self {
+        self.text_options.file_encoding = file_encoding;
+        self
+    }
+
+    /// Writes a `manifest.json` describing every converted file into the output root, disabled by
+    /// default.
+    pub fn write_manifest(mut self, write_manifest: bool) -> Self {
+        self.write_manifest = write_manifest;
+        self
+    }
+
+    /// Additionally writes the outputs and a manifest into the ZIP archive `filename`. The archive
+    /// is reproducible: the entries are sorted and have fixed timestamps.
+    pub fn output_archive(mut self, filename: impl Into<PathBuf>) -> Self {
+        self.output_archive = Some(filename.into());
+        self
+    }
+
+    /// Only converts the files whose input changed since the last incremental conversion and
+    /// deletes the outputs whose input no longer exists, disabled by default. The state is kept in
+    /// a hidden JSON file in the output root, changing the options of a resource kind reconverts
+    /// all files of that kind.
+    pub fn incremental(mut self, incremental: bool) -> Self {
+        self.incremental = incremental;
+        self
+    }
+
+    /// Converts all selected resources. Failures of individual files are reported in the
+    /// returned [`FileReport`]s, only a missing or unreadable input directory is an error.
+    pub fn convert(&self) -> Result<Vec<FileReport>> {
+        let names: Vec<&str> = ResourceKind::ALL
+            .iter()
+            .map(|&kind| self.dir_names(kind).0)
+            .collect();
+        let (source, game_dir) = open_game_source(&self.input_root, &names)?;
+
+        let state_filename = self
+            .effective_output_root()
+            .join(state_filename(self.direction));
+        let mut state = self
+            .incremental
+            .then(|| ConversionState::load(&state_filename));
+
+        let mut reports = Vec::new();
+        for &kind in &self.resource_kinds {
+            let fingerprint = self.options_fingerprint(kind);
+            let mut kind_reports = match &state {
+                Some(state) => self.convert_kind(
+                    kind,
+                    source.as_ref(),
+                    &game_dir,
+                    Some(&IncrementalContext {
+                        previous: state.entries_of_kind(kind).cloned().collect(),
+                        reuse_outputs: state.fingerprints.get(&kind) == Some(&fingerprint),
+                    }),
+                )?,
+                None => self.convert_kind(kind, source.as_ref(), &game_dir, None)?,
+            };
+
+            if let Some(state) = &mut state {
+                state.replace_kind(kind, state_entries(&kind_reports));
+                state.fingerprints.insert(kind, fingerprint);
+            }
+            reports.append(&mut kind_reports);
+        }
+
+        if let Some(state) = state {
+            state.save(&state_filename)?;
+        }
+
+        self.write_summary_files(&reports)?;
+
+        Ok(reports)
+    }
+
+    /// Writes the enabled manifest and output archive describing `reports` and returns their
+    /// filenames.
+    pub(crate) fn write_summary_files(&self, reports: &[FileReport]) -> Result<Vec<PathBuf>> {
+        let output_root = self.effective_output_root();
+        let manifest = Manifest::new(self.direction, &self.input_root, &output_root, reports);
+        let mut filenames = Vec::new();
+
+        if self.write_manifest {
+            let filename = output_root.join(MANIFEST_FILENAME);
+            manifest.write(&filename)?;
+            filenames.push(filename);
+        }
+
+        if let Some(filename) = &self.output_archive {
+            write_output_archive(filename, &output_root, manifest, reports)?;
+            filenames.push(filename.clone());
+        }
+
+        Ok(filenames)
+    }
+
+    /// Disables the manifest and the output archive, e.g. to write them once for several calls
+    /// of [`Converter::convert`].
+    pub(crate) fn without_summary_files(mut self) -> Self {
+        self.write_manifest = false;
+        self.output_archive = None;
+        self
+    }
+
+    /// The output root, by default the game directory or, for archives and disk images, a
+    /// directory named like the archive next to it.
+    fn effective_output_root(&self) -> PathBuf {
+        match &self.output_root {
+            Some(output_root) => output_root.clone(),
+            None if self.input_root.is_file() => self.input_root.with_extension(""),
+            None => self.input_root.clone(),
+        }
+    }
+
+    /// The fingerprint of the options that affect the outputs of `kind`.
+    fn options_fingerprint(&self, kind: ResourceKind) -> String {
+        match kind {
+            ResourceKind::Graphics => options_fingerprint(&self.graphics_options),
+            ResourceKind::Texts => options_fingerprint(&self.text_options),
+        }
+    }
+
+    /// The names of the input and output directories of `kind` in the current direction.
+    fn dir_names(&self, kind: ResourceKind) -> (&str, &str) {
+        let layout = &self.layout;
+        let (game_dir, converted_dir) = match kind {
+            ResourceKind::Graphics => (&layout.graphics_input, &layout.graphics_output),
+            ResourceKind::Texts => (&layout.text_input, &layout.text_output),
+        };
+
+        match self.direction {
+            Direction::Export => (game_dir, converted_dir),
+            Direction::Import => (converted_dir, game_dir),
+        }
+    }
+
+    fn convert_kind(
+        &self,
+        kind: ResourceKind,
+        source: &dyn InputSource,
+        game_dir: &Path,
+        incremental: Option<&IncrementalContext>,
+    ) -> Result<Vec<FileReport>> {
+        let (input_name, output_name) = self.dir_names(kind);
+        let input = Input {
+            source,
+            root: &self.input_root,
+            dir: source.find_dir_ignoring_case(game_dir, input_name),
+        };
+        let output_dir = find_dir_ignoring_case(&self.effective_output_root(), output_name);
+
+        match (kind, self.direction) {
+            (ResourceKind::Graphics, Direction::Export) => convert_dir(
+                kind,
+                &input,
+                "PCX",
+                &output_dir,
+                "PNG",
+                &|input_filename, input_contents, output_filename, warnings| {
+                    convert_pcx(
+                        input_filename,
+                        input_contents,
+                        output_filename,
+                        &self.graphics_options,
+                        warnings,
+                    )
+                },
+                incremental,
+            ),
+            (ResourceKind::Graphics, Direction::Import) => convert_dir(
+                kind,
+                &input,
+                "PNG",
+                &output_dir,
+                "PCX",
+                &convert_png,
+                incremental,
+            ),
+            (ResourceKind::Texts, Direction::Export) => convert_dir(
+                kind,
+                &input,
+                "TCT",
+                &output_dir,
+                "TXT",
+                &|input_filename, input_contents, output_filename, warnings| {
+                    convert_txt(
+                        input_filename,
+                        input_contents,
+                        output_filename,
+                        &self.text_options,
+                        warnings,
+                    )
+                },
+                incremental,
+            ),
+            (ResourceKind::Texts, Direction::Import) => convert_dir(
+                kind,
+                &input,
+                "TXT",
+                &output_dir,
+                "TCT",
+                &|input_filename, input_contents, output_filename, warnings| {
+                    convert_tct(
+                        input_filename,
+                        input_contents,
+                        output_filename,
+                        &self.text_options,
+                        warnings,
+                    )
+                },
+                incremental,
+            ),
+        }
+    }
+
+    /// Interprets the input root as a directory containing any number of game installations, e.g.
+    /// different versions of the game in nested directories. Every installation is converted with
+    /// the settings of this converter, only the resource kinds present in an installation are
+    /// converted. With an output root the outputs are written into a mirrored directory tree.
+    pub fn convert_batch(&self) -> Result<Vec<BatchReport>> {
+        if self.output_archive.is_some() {
+            bail!("An output archive can not be written for a batch conversion.");
+        }
+
+        let installations = self.find_installations()?;
+
+        Ok(installations
+            .into_iter()
+            .map(|installation| {
+                let relative_path = installation
+                    .strip_prefix(&self.input_root)
+                    .unwrap_or(&installation);
+
+                let mut converter = self.clone();
+                converter.resource_kinds = self
+                    .resource_kinds
+                    .iter()
+                    .copied()
+                    .filter(|&kind| {
+                        find_dir_ignoring_case(&installation, self.dir_names(kind).0).is_dir()
+                    })
+                    .collect();
+                converter.output_root = self
+                    .output_root
+                    .as_ref()
+                    .map(|output_root| output_root.join(relative_path));
+                converter.input_root = installation.clone();
+
+                BatchReport {
+                    result: converter.convert(),
+                    installation,
+                }
+            })
+            .collect())
+    }
+
+    /// Returns the sorted paths of all directories below the input root, including the input
+    /// root itself, that contain the input directory of one of the resource kinds. The
+    /// installations themselves are not searched any further.
+    pub fn find_installations(&self) -> Result<Vec<PathBuf>> {
+        let mut installations = Vec::new();
+        let mut pending = vec![self.input_root.clone()];
+
+        while let Some(dir) = pending.pop() {
+            let is_installation = self
+                .resource_kinds
+                .iter()
+                .any(|&kind| find_dir_ignoring_case(&dir, self.dir_names(kind).0).is_dir());
+            if is_installation {
+                installations.push(dir);
+                continue;
+            }
+
+            let entries = read_dir(&dir)
+                .wrap_err_with(|| format!("Unable to read directory '{}'.", dir.display()))?;
+            for entry in entries {
+                let entry = entry.wrap_err_with(|| {
+                    format!("Unable to read directory entry in '{}'.", dir.display())
+                })?;
+                let is_output_root = self.output_root.as_deref() == Some(entry.path().as_path());
+                if entry.file_type().is_ok_and(|t| t.is_dir()) && !is_output_root {
+                    pending.push(entry.path());
+                }
+            }
+        }
+
+        installations.sort();
+        Ok(installations)
+    }
+}
+
+// -------------------------------------------------------------------------------------------------
+
+/// What is known about the outputs of the last incremental conversion of a resource kind.
+struct IncrementalContext {
+    previous: Vec<StateEntry>,
+    /// Whether the outputs were written with the current options and can be kept.
+    reuse_outputs: bool,
+}
+
+impl IncrementalContext {
+    /// Returns the state entry of the output if it is up to date.
+    fn up_to_date_entry(&self, output_filename: &Path, input_hash: &str) -> Option<&StateEntry> {
+        if !self.reuse_outputs || !output_filename.is_file() {
+            return None;
+        }
+
+        self.previous.iter().find(|entry| {
+            entry.input_sha256 == input_hash && entry.output == file_name(output_filename)
+        })
+    }
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+/// The state entries of the outputs that exist and match their input after a conversion.
+fn state_entries(reports: &[FileReport]) -> Vec<StateEntry> {
+    reports
+        .iter()
+        .filter(|report| {
+            matches!(
+                report.result,
+                Ok(FileSummary::Image { .. } | FileSummary::Text { .. } | FileSummary::UpToDate)
+            )
+        })
+        .filter_map(|report| {
+            let summary = match &report.result {
+                Ok(FileSummary::UpToDate) => report.previous_summary.clone(),
+                Ok(summary) => Some(summary.clone()),
+                Err(_) => None,
+            };
+
+            Some(StateEntry {
+                kind: report.kind,
+                source: file_name(&report.input_path),
+                output: file_name(&report.output_path),
+                input_sha256: report.input_hash.clone()?,
+                summary,
+                warnings: report.warnings.clone(),
+            })
+        })
+        .collect()
+}
+
+// -------------------------------------------------------------------------------------------------
+
+/// Converts the input file to the output file, warnings are appended to the given vector.
+type ConversionFn<'a> =
+    dyn Fn(&Path, &[u8], &Path, &mut Vec<String>) -> Result<FileSummary> + Sync + 'a;
+
+/// The input directory of a resource kind.
+struct Input<'a> {
+    source: &'a dyn InputSource,
+    /// The directory, archive or disk image the source was opened from.
+    root: &'a Path,
+    /// The path of the directory in the source.
+    dir: PathBuf,
+}
+
+fn convert_dir(
+    kind: ResourceKind,
+    input: &Input<'_>,
+    input_extension: &str,
+    output_path: &Path,
+    output_extension: &str,
+    conversion_fn: &ConversionFn<'_>,
+    incremental: Option<&IncrementalContext>,
+) -> Result<Vec<FileReport>> {
+    let input_filenames = input
+        .source
+        .list_files_with_extension(&input.dir, input_extension)?;
+
+    let _ = DirBuilder::new().recursive(true).create(output_path);
+
+    let files_to_convert = {
+        let mut files_to_convert = Vec::new();
+
+        for input_filename in input_filenames {
+            let output_filename =
+                to_output_filename(&input_filename, output_path, output_extension).wrap_err_with(
+                    || {
+                        format!(
+                            "Unable to create output filename for input file '{}'.",
+                            input_filename.display()
+                        )
+                    },
+                )?;
+
+            files_to_convert.push((input_filename, output_filename));
+        }
+
+        files_to_convert
+    };
+
+    let mut reports: Vec<FileReport> = files_to_convert
+        .par_iter()
+        .map(|(source_filename, output_filename)| {
+            let input_filename = input.root.join(source_filename);
+            let mut warnings = Vec::new();
+            let mut previous_summary = None;
+            let input_contents = input.source.read_file(source_filename);
+            let input_hash = input_contents
+                .as_ref()
+                .ok()
+                .map(|contents| sha256_hex(contents));
+
+            let up_to_date_entry = match (incremental, &input_hash) {
+                (Some(incremental), Some(input_hash)) => {
+                    incremental.up_to_date_entry(output_filename, input_hash)
+                }
+                _ => None,
+            };
+
+            let result = match input_contents {
+                Ok(input_contents) => match up_to_date_entry {
+                    Some(entry) => {
+                        warnings.clone_from(&entry.warnings);
+                        previous_summary.clone_from(&entry.summary);
+                        Ok(FileSummary::UpToDate)
+                    }
+                    None => conversion_fn(
+                        &input_filename,
+                        &input_contents,
+                        output_filename,
+                        &mut warnings,
+                    ),
+                },
+                Err(err) => Err(err),
+            }
+            .wrap_err_with(|| {
+                format!(
+                    "Unable to convert '{}' to '{}'.",
+                    input_filename.display(),
+                    output_filename.display()
+                )
+            });
+
+            FileReport {
+                kind,
+                input_path: input_filename,
+                output_path: output_filename.clone(),
+                result,
+                warnings,
+                input_hash,
+                previous_summary,
+            }
+        })
+        .collect();
+
+    if let Some(incremental) = incremental {
+        for entry in &incremental.previous {
+            let output_filename = output_path.join(&entry.output);
+            let still_converted = files_to_convert
+                .iter()
+                .any(|(_, converted)| *converted == output_filename);
+            if still_converted || !output_filename.is_file() {
+                continue;
+            }
+
+            let result = remove_file(&output_filename)
+                .map(|_| FileSummary::Removed)
+                .wrap_err_with(|| format!("Unable to remove '{}'.", output_filename.display()));
+
+            reports.push(FileReport {
+                kind,
+                input_path: input.root.join(&input.dir).join(&entry.source),
+                output_path: output_filename,
+                result,
+                warnings: Vec::new(),
+                input_hash: None,
+                previous_summary: None,
+            });
+        }
+    }
+
+    Ok(reports)
+}
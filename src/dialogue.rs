@@ -0,0 +1,290 @@
+use crate::{
+    codec::CodecTable,
+    fs_util::{create_output_file, list_files_with_extension, read_file_contents},
+    source::{DirectorySource, InputSource},
+    text::{decode_tct_with_table, encode_tct_with_table, UnmappedBytes},
+};
+use eyre::{Result, WrapErr};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::DirBuilder,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+// -------------------------------------------------------------------------------------------------
+
+/// A part of a dialogue entry, each item is a single line of the dialogue file.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum DialogueItem {
+    /// A line like `Jonathan: Hallo!`.
+    Line { speaker: String, text: String },
+    /// An answer the player can choose, like `1. Wer bist du?`.
+    Choice { number: u32, text: String },
+    /// Any other line.
+    Narration { text: String },
+}
+
+/// The lines between two separators.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DialogueEntry {
+    pub items: Vec<DialogueItem>,
+    /// The line that ends the entry, empty for a blank line and `None` for the last entry.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub separator: Option<String>,
+}
+
+/// The structure of a dialogue file like `D4.TCT`, recognized from the layout of its lines. Blank
+/// lines and lines like `-----` separate the entries. [`Dialogue::to_text`] restores the original
+/// text, so edited dialogues can be converted back.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Dialogue {
+    pub entries: Vec<DialogueEntry>,
+}
+
+/// The file format of structured dialogues.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum DialogueFormat {
+    Json,
+    Yaml,
+}
+
+impl DialogueFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            DialogueFormat::Json => "json",
+            DialogueFormat::Yaml => "yaml",
+        }
+    }
+}
+
+fn is_separator(line: &str) -> bool {
+    let mut chars = line.chars();
+    match chars.next() {
+        None => true,
+        Some(first) => {
+            line.chars().count() >= 3
+                && ['-', '=', '*', '#', '~', '_'].contains(&first)
+                && chars.all(|c| c == first)
+        }
+    }
+}
+
+/// Speakers are names of up to three capitalized words.
+fn is_speaker(name: &str) -> bool {
+    let words: Vec<&str> = name.split(' ').collect();
+    name.chars().count() <= 24
+        && words.len() <= 3
+        && words.iter().all(|word| {
+            word.chars().next().is_some_and(char::is_uppercase)
+                && word
+                    .chars()
+                    .all(|c| c.is_alphanumeric() || c == '-' || c == '.')
+        })
+}
+
+fn parse_item(line: &str) -> DialogueItem {
+    if let Some((number, text)) = line.split_once(". ") {
+        if let Ok(parsed) = number.parse::<u32>() {
+            // Only the canonical form can be restored, e.g. `01. ` stays narration.
+            if parsed.to_string() == number {
+                return DialogueItem::Choice {
+                    number: parsed,
+                    text: text.to_owned(),
+                };
+            }
+        }
+    }
+
+    if let Some((speaker, text)) = line.split_once(": ") {
+        if is_speaker(speaker) {
+            return DialogueItem::Line {
+                speaker: speaker.to_owned(),
+                text: text.to_owned(),
+            };
+        }
+    }
+
+    DialogueItem::Narration {
+        text: line.to_owned(),
+    }
+}
+
+impl DialogueItem {
+    fn to_line(&self) -> String {
+        match self {
+            DialogueItem::Line { speaker, text } => format!("{}: {}", speaker, text),
+            DialogueItem::Choice { number, text } => format!("{}. {}", number, text),
+            DialogueItem::Narration { text } => text.clone(),
+        }
+    }
+}
+
+impl Dialogue {
+    /// Recognizes the structure of `text`, whose lines are separated by `'\n'`.
+    pub fn parse(text: &str) -> Self {
+        let mut entries = Vec::new();
+        let mut items = Vec::new();
+
+        for line in text.split('\n') {
+            if is_separator(line) {
+                entries.push(DialogueEntry {
+                    items: std::mem::take(&mut items),
+                    separator: Some(line.to_owned()),
+                });
+            } else {
+                items.push(parse_item(line));
+            }
+        }
+
+        // A text ending with a line break results in an empty last entry.
+        if !items.is_empty() {
+            entries.push(DialogueEntry {
+                items,
+                separator: None,
+            });
+        }
+
+        Self { entries }
+    }
+
+    /// Formats the dialogue as text, lines are separated by `'\n'`.
+    pub fn to_text(&self) -> String {
+        let mut lines = Vec::new();
+        for entry in &self.entries {
+            lines.extend(entry.items.iter().map(DialogueItem::to_line));
+            lines.extend(entry.separator.clone());
+        }
+        lines.join("\n")
+    }
+
+    pub fn serialize(&self, format: DialogueFormat) -> Result<String> {
+        match format {
+            DialogueFormat::Json => serde_json::to_string_pretty(self).map_err(Into::into),
+            DialogueFormat::Yaml => serde_yaml::to_string(self).map_err(Into::into),
+        }
+    }
+
+    pub fn deserialize(s: &str, format: DialogueFormat) -> Result<Self> {
+        match format {
+            DialogueFormat::Json => serde_json::from_str(s).map_err(Into::into),
+            DialogueFormat::Yaml => serde_yaml::from_str(s).map_err(Into::into),
+        }
+    }
+}
+
+// -------------------------------------------------------------------------------------------------
+
+/// Returns the dialogue files among `filenames`, whose names start with `D`.
+fn dialogue_files(filenames: Vec<PathBuf>) -> impl Iterator<Item = PathBuf> {
+    filenames.into_iter().filter(|filename| {
+        filename
+            .file_name()
+            .and_then(|name| name.to_str())
+            .is_some_and(|name| name.starts_with(['D', 'd']))
+    })
+}
+
+/// The dialogue files that were converted and the ones that failed.
+#[derive(Debug, Default)]
+pub struct DialogueReport {
+    pub converted: Vec<(PathBuf, PathBuf)>,
+    pub failures: Vec<(PathBuf, eyre::Report)>,
+}
+
+/// Writes the dialogue files in `text_dir` as structured files into `output_dir`.
+pub fn export_dialogues(
+    text_dir: &Path,
+    output_dir: &Path,
+    format: DialogueFormat,
+    table: &CodecTable,
+) -> Result<DialogueReport> {
+    export_dialogues_from_source(
+        &DirectorySource::new(text_dir),
+        text_dir,
+        Path::new(""),
+        output_dir,
+        format,
+        table,
+    )
+}
+
+/// Writes the dialogue files in the directory `text_dir` of `source`, which was opened from
+/// `root`, as structured files into `output_dir`.
+pub(crate) fn export_dialogues_from_source(
+    source: &dyn InputSource,
+    root: &Path,
+    text_dir: &Path,
+    output_dir: &Path,
+    format: DialogueFormat,
+    table: &CodecTable,
+) -> Result<DialogueReport> {
+    let mut report = DialogueReport::default();
+
+    let filenames = source.list_files_with_extension(text_dir, "TCT")?;
+    DirBuilder::new()
+        .recursive(true)
+        .create(output_dir)
+        .wrap_err_with(|| format!("Unable to create '{}'.", output_dir.display()))?;
+
+    for source_filename in dialogue_files(filenames) {
+        let filename = root.join(&source_filename);
+        let output_filename = output_dir
+            .join(filename.file_name().unwrap())
+            .with_extension(format.extension());
+
+        let result = source.read_file(&source_filename).and_then(|contents| {
+            let text = decode_tct_with_table(&contents, table, UnmappedBytes::Reject)
+                .wrap_err_with(|| format!("Unable to decode '{}'.", filename.display()))?
+                .text;
+            let serialized = Dialogue::parse(&text).serialize(format)?;
+            create_output_file(&output_filename)?
+                .write_all(serialized.as_bytes())
+                .wrap_err_with(|| format!("Unable to write to '{}'.", output_filename.display()))
+        });
+
+        match result {
+            Ok(()) => report.converted.push((filename, output_filename)),
+            Err(err) => report.failures.push((filename, err)),
+        }
+    }
+
+    Ok(report)
+}
+
+/// Converts the structured files in `dialogue_dir` back to TCT files in `text_dir`.
+pub fn import_dialogues(
+    dialogue_dir: &Path,
+    text_dir: &Path,
+    format: DialogueFormat,
+    table: &CodecTable,
+) -> Result<DialogueReport> {
+    let mut report = DialogueReport::default();
+
+    let extension_upper = format.extension().to_uppercase();
+    for filename in dialogue_files(list_files_with_extension(dialogue_dir, &extension_upper)?) {
+        let output_filename = text_dir
+            .join(filename.file_name().unwrap())
+            .with_extension("TCT");
+
+        let result = read_file_contents(&filename).and_then(|contents| {
+            let dialogue = std::str::from_utf8(&contents)
+                .map_err(Into::into)
+                .and_then(|s| Dialogue::deserialize(s, format))
+                .wrap_err_with(|| format!("Unable to read '{}'.", filename.display()))?;
+            let data = encode_tct_with_table(&dialogue.to_text(), table, UnmappedBytes::Reject)
+                .wrap_err_with(|| format!("Unable to encode '{}'.", filename.display()))?;
+            create_output_file(&output_filename)?
+                .write_all(&data)
+                .wrap_err_with(|| format!("Unable to write to '{}'.", output_filename.display()))
+        });
+
+        match result {
+            Ok(()) => report.converted.push((filename, output_filename)),
+            Err(err) => report.failures.push((filename, err)),
+        }
+    }
+
+    Ok(report)
+}
@@ -0,0 +1,227 @@
+use eyre::{bail, Result};
+
+// -------------------------------------------------------------------------------------------------
+
+const DIR_ENTRY_SIZE: usize = 32;
+
+const ATTRIBUTE_VOLUME_LABEL: u8 = 0x08;
+const ATTRIBUTE_DIRECTORY: u8 = 0x10;
+const ATTRIBUTE_LONG_NAME: u8 = 0x0f;
+
+// -------------------------------------------------------------------------------------------------
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum FatType {
+    Fat12,
+    Fat16,
+}
+
+/// A file or directory in a FAT disk image.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct FatEntry {
+    /// The 8.3 name, e.g. `ORT1.PCX`.
+    pub name: String,
+    pub is_dir: bool,
+    first_cluster: u16,
+    size: u32,
+}
+
+/// A raw FAT12 or FAT16 disk image like the `.img` files of floppy disks, kept in memory.
+pub(crate) struct FatImage {
+    data: Vec<u8>,
+    fat_type: FatType,
+    cluster_size: usize,
+    fat_offset: usize,
+    root_dir_offset: usize,
+    root_dir_entries: usize,
+    data_offset: usize,
+    cluster_count: usize,
+}
+
+fn read_u16(data: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([data[offset], data[offset + 1]])
+}
+
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([
+        data[offset],
+        data[offset + 1],
+        data[offset + 2],
+        data[offset + 3],
+    ])
+}
+
+impl FatImage {
+    /// Parses the boot sector of the image.
+    pub fn new(data: Vec<u8>) -> Result<Self> {
+        if data.len() < 512 {
+            bail!("The disk image is too small to contain a boot sector.");
+        }
+
+        let bytes_per_sector = read_u16(&data, 11) as usize;
+        let sectors_per_cluster = data[13] as usize;
+        let reserved_sectors = read_u16(&data, 14) as usize;
+        let fat_count = data[16] as usize;
+        let root_dir_entries = read_u16(&data, 17) as usize;
+        let total_sectors = match read_u16(&data, 19) {
+            0 => read_u32(&data, 32) as usize,
+            sectors => sectors as usize,
+        };
+        let sectors_per_fat = read_u16(&data, 22) as usize;
+
+        if ![512, 1024, 2048, 4096].contains(&bytes_per_sector)
+            || !sectors_per_cluster.is_power_of_two()
+            || fat_count == 0
+            || sectors_per_fat == 0
+        {
+            bail!("The disk image does not contain a valid FAT boot sector.");
+        }
+
+        let fat_offset = reserved_sectors * bytes_per_sector;
+        let root_dir_offset = fat_offset + fat_count * sectors_per_fat * bytes_per_sector;
+        let data_offset = root_dir_offset + root_dir_entries * DIR_ENTRY_SIZE;
+        let cluster_size = sectors_per_cluster * bytes_per_sector;
+        let total_size = (total_sectors * bytes_per_sector).min(data.len());
+        let cluster_count = total_size.saturating_sub(data_offset) / cluster_size;
+
+        let fat_type = match cluster_count {
+            0..=4084 => FatType::Fat12,
+            4085..=65524 => FatType::Fat16,
+            _ => bail!("FAT32 disk images are not supported."),
+        };
+
+        if data_offset > data.len() {
+            bail!("The disk image is truncated.");
+        }
+
+        Ok(Self {
+            data,
+            fat_type,
+            cluster_size,
+            fat_offset,
+            root_dir_offset,
+            root_dir_entries,
+            data_offset,
+            cluster_count,
+        })
+    }
+
+    fn next_cluster(&self, cluster: u16) -> Option<u16> {
+        let cluster = cluster as usize;
+        let next = match self.fat_type {
+            FatType::Fat12 => {
+                let offset = self.fat_offset + cluster + cluster / 2;
+                let value = read_u16(self.data.get(offset..offset + 2)?, 0);
+                if cluster % 2 == 1 {
+                    value >> 4
+                } else {
+                    value & 0x0fff
+                }
+            }
+            FatType::Fat16 => {
+                let offset = self.fat_offset + cluster * 2;
+                read_u16(self.data.get(offset..offset + 2)?, 0)
+            }
+        };
+
+        let end_of_chain = match self.fat_type {
+            FatType::Fat12 => 0x0ff8,
+            FatType::Fat16 => 0xfff8,
+        };
+        if next < 2 || next >= end_of_chain {
+            None
+        } else {
+            Some(next)
+        }
+    }
+
+    /// Reads the clusters of the chain starting at `first_cluster`, at most `max_size` bytes.
+    fn read_chain(&self, first_cluster: u16, max_size: usize) -> Result<Vec<u8>> {
+        let mut contents = Vec::new();
+        let mut cluster = Some(first_cluster).filter(|&cluster| cluster >= 2);
+
+        while let Some(current) = cluster {
+            if contents.len() >= max_size {
+                break;
+            }
+            // A chain can not be longer than the number of clusters, otherwise it contains a loop.
+            if contents.len() > self.cluster_count * self.cluster_size {
+                bail!(
+                    "The cluster chain starting at {} is corrupt.",
+                    first_cluster
+                );
+            }
+
+            let offset = self.data_offset + (current as usize - 2) * self.cluster_size;
+            match self.data.get(offset..offset + self.cluster_size) {
+                Some(cluster_data) => contents.extend_from_slice(cluster_data),
+                None => bail!("Cluster {} is outside of the disk image.", current),
+            }
+            cluster = self.next_cluster(current);
+        }
+
+        if contents.len() < max_size && max_size != usize::MAX {
+            bail!(
+                "The cluster chain starting at {} is truncated.",
+                first_cluster
+            );
+        }
+        contents.truncate(max_size);
+        Ok(contents)
+    }
+
+    fn parse_entries(data: &[u8]) -> Vec<FatEntry> {
+        let mut entries = Vec::new();
+
+        for entry in data.chunks_exact(DIR_ENTRY_SIZE) {
+            match entry[0] {
+                0x00 => break,
+                0xe5 | b'.' => continue,
+                _ => {}
+            }
+
+            let attributes = entry[11];
+            if attributes == ATTRIBUTE_LONG_NAME || attributes & ATTRIBUTE_VOLUME_LABEL != 0 {
+                continue;
+            }
+
+            let base_name = String::from_utf8_lossy(&entry[0..8]).trim_end().to_owned();
+            let extension = String::from_utf8_lossy(&entry[8..11]).trim_end().to_owned();
+            let name = if extension.is_empty() {
+                base_name
+            } else {
+                format!("{}.{}", base_name, extension)
+            };
+
+            entries.push(FatEntry {
+                name,
+                is_dir: attributes & ATTRIBUTE_DIRECTORY != 0,
+                first_cluster: read_u16(entry, 26),
+                size: read_u32(entry, 28),
+            });
+        }
+
+        entries
+    }
+
+    pub fn root_entries(&self) -> Result<Vec<FatEntry>> {
+        match self.data.get(
+            self.root_dir_offset..self.root_dir_offset + self.root_dir_entries * DIR_ENTRY_SIZE,
+        ) {
+            Some(root_dir) => Ok(Self::parse_entries(root_dir)),
+            None => bail!("The root directory is outside of the disk image."),
+        }
+    }
+
+    /// Returns the entries of the subdirectory `entry`.
+    pub fn dir_entries(&self, entry: &FatEntry) -> Result<Vec<FatEntry>> {
+        Ok(Self::parse_entries(
+            &self.read_chain(entry.first_cluster, usize::MAX)?,
+        ))
+    }
+
+    /// Returns the contents of the file `entry`.
+    pub fn file_contents(&self, entry: &FatEntry) -> Result<Vec<u8>> {
+        self.read_chain(entry.first_cluster, entry.size as usize)
+    }
+}
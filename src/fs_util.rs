@@ -0,0 +1,131 @@
+use data_encoding::HEXUPPER;
+use eyre::{bail, Result, WrapErr};
+use ring::digest::{digest, SHA256};
+use std::{
+    fs::{read_dir, File, OpenOptions},
+    io::Read,
+    path::{Path, PathBuf},
+};
+
+// -------------------------------------------------------------------------------------------------
+
+pub(crate) fn is_file_with_extension(path: &Path, extension_upper: &str) -> bool {
+    path.is_file() && has_extension(path, extension_upper)
+}
+
+/// Returns whether the extension of `path` matches `extension_upper`, ignoring case.
+pub(crate) fn has_extension(path: &Path, extension_upper: &str) -> bool {
+    path.extension().is_some_and(|e| {
+        e.to_str()
+            .is_some_and(|e| e.to_uppercase() == extension_upper)
+    })
+}
+
+pub(crate) fn create_output_file(path: &Path) -> Result<File> {
+    let file = OpenOptions::new()
+        .write(true)
+        .truncate(true)
+        .create(true)
+        .open(path)
+        .wrap_err_with(|| {
+            format!(
+                "Unable to create '{}'. Is the path writable?",
+                path.display()
+            )
+        })?;
+
+    Ok(file)
+}
+
+pub(crate) fn to_output_filename(
+    input_path: &Path,
+    output_path: &Path,
+    output_extension: &str,
+) -> Result<PathBuf> {
+    let stem = match input_path.file_stem() {
+        Some(stem) => stem,
+        None => bail!(
+            "Input path '{}' does not have a stem.",
+            input_path.display()
+        ),
+    };
+
+    let mut output_filename = PathBuf::new();
+    output_filename.push(output_path);
+    output_filename.push(stem);
+    output_filename.set_extension(output_extension);
+    Ok(output_filename)
+}
+
+pub(crate) fn read_file_contents(filename: &Path) -> Result<Vec<u8>> {
+    let mut input_file = OpenOptions::new()
+        .read(true)
+        .open(filename)
+        .wrap_err_with(|| format!("Unable to open input file '{}'.", filename.display()))?;
+    let mut contents = Vec::new();
+    let _ = input_file
+        .read_to_end(&mut contents)
+        .wrap_err_with(|| format!("Unable to read input file '{}'.", filename.display()))?;
+    Ok(contents)
+}
+
+/// Returns the sorted paths of all files in `dir` with the given extension.
+pub(crate) fn list_files_with_extension(dir: &Path, extension_upper: &str) -> Result<Vec<PathBuf>> {
+    let dir_reader = read_dir(dir).wrap_err_with(|| {
+        format!(
+            "Unable to read directory '{}'. Is the provided path correct?",
+            dir.display()
+        )
+    })?;
+
+    let mut filenames = Vec::new();
+    for entry in dir_reader {
+        let entry = entry
+            .wrap_err_with(|| format!("Unable to read directory entry in '{}'.", dir.display()))?;
+        let filename = entry.path();
+        if is_file_with_extension(&filename, extension_upper) {
+            filenames.push(filename);
+        }
+    }
+
+    filenames.sort();
+    Ok(filenames)
+}
+
+/// Returns the SHA-256 digest of `data` as upper case hex string.
+pub(crate) fn sha256_hex(data: &[u8]) -> String {
+    HEXUPPER.encode(digest(&SHA256, data).as_ref())
+}
+
+/// Returns the path of the directory `name` in `parent`. If it does not exist under that exact
+/// name, an existing directory whose name only differs in case is returned instead, e.g.
+/// `grafik` for `GRAFIK` on case sensitive file systems.
+pub(crate) fn find_dir_ignoring_case(parent: &Path, name: &str) -> PathBuf {
+    let path = parent.join(name);
+    if path.is_dir() {
+        return path;
+    }
+
+    read_dir(parent)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .map(|entry| entry.path())
+        .find(|candidate| {
+            candidate.is_dir()
+                && candidate
+                    .file_name()
+                    .and_then(|n| n.to_str())
+                    .is_some_and(|n| n.eq_ignore_ascii_case(name))
+        })
+        .unwrap_or(path)
+}
+
+/// Returns `path` relative to `root` with `/` as separator, as used in manifests and archives.
+pub(crate) fn relative_path(path: &Path, root: &Path) -> String {
+    let path = path.strip_prefix(root).unwrap_or(path);
+    path.components()
+        .map(|component| component.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
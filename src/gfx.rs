@@ -0,0 +1,507 @@
+use crate::{
+    converter::FileSummary,
+    fs_util::{create_output_file, is_file_with_extension, read_file_contents},
+    palette::{detect_palette_depth, normalize_palette, palette_hash, PaletteDepth, PaletteMode},
+    pcx::{
+        decode_pcx, decode_pcx_with_mode, encode_pcx, format_signature, parse_signature,
+        read_vga_palette, DecodeMode, PcxImage, PcxPixels,
+    },
+};
+use eyre::{bail, Result, WrapErr};
+use std::{borrow::Cow, io::Write, path::Path};
+
+// -------------------------------------------------------------------------------------------------
+
+const PNG_SIGNATURE_KEYWORD: &str = "Jonathan PCX signature";
+const PNG_PALETTE_DEPTH_KEYWORD: &str = "Jonathan PCX palette depth";
+const PNG_SIX_BIT_PALETTE: &str = "6";
+const PNG_EIGHT_BIT_PALETTE: &str = "8";
+
+// -------------------------------------------------------------------------------------------------
+
+/// The settings of the PCX to PNG conversion.
+#[derive(Clone, Copy, Debug, Default)]
+pub(crate) struct GraphicsOptions {
+    pub decode_mode: DecodeMode,
+    pub transparent_color: TransparentColor,
+    pub transparency_output: TransparencyOutput,
+    pub palette_mode: PaletteMode,
+}
+
+/// A decoded 256 color image of the game.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct JonathanImage {
+    pub width: u32,
+    pub height: u32,
+    /// One palette index per pixel, row by row.
+    pub indices: Vec<u8>,
+    pub palette: [[u8; 3]; 256],
+    /// The obfuscated first four bytes of the PCX file the image was decoded from.
+    pub signature: Option<[u8; 4]>,
+    /// The depth of the color values in the PCX file. `palette` always holds 8 bit color values,
+    /// [`encode_pcx`] reduces them to 6 bit again for [`PaletteDepth::SixBit`].
+    pub palette_depth: PaletteDepth,
+}
+
+/// Which palette index of the converted images is transparent.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum TransparentColor {
+    /// The images are fully opaque.
+    #[default]
+    None,
+    /// The given palette index is transparent.
+    Index(u8),
+    /// The color key is detected from the corners of each image, see [`detect_color_key`].
+    AutoDetect,
+}
+
+/// How transparency is stored in the converted PNG files.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum TransparencyOutput {
+    /// Indexed PNG files with a `tRNS` chunk, these can still be imported.
+    #[default]
+    Trns,
+    /// RGBA PNG files.
+    Rgba,
+}
+
+enum PngPixels<'a> {
+    Indexed {
+        bit_depth: png::BitDepth,
+        palette: &'a [[u8; 3]],
+        transparent_index: Option<u8>,
+        packed_indices: Vec<u8>,
+    },
+    Rgb(&'a [u8]),
+    Rgba(&'a [u8]),
+}
+
+fn write_png(
+    width: u32,
+    height: u32,
+    signature: Option<[u8; 4]>,
+    palette_depth: Option<PaletteDepth>,
+    pixels: PngPixels<'_>,
+) -> Result<Vec<u8>> {
+    let mut png_data = Vec::new();
+
+    {
+        let mut png_encoder = png::Encoder::new(&mut png_data, width, height);
+        png_encoder.set_depth(png::BitDepth::Eight);
+
+        let data = match pixels {
+            PngPixels::Indexed {
+                bit_depth,
+                palette,
+                transparent_index,
+                packed_indices,
+            } => {
+                png_encoder.set_color(png::ColorType::Indexed);
+                png_encoder.set_depth(bit_depth);
+                png_encoder.set_palette(palette.concat());
+                if let Some(transparent_index) = transparent_index {
+                    let mut trns = vec![0xffu8; transparent_index as usize + 1];
+                    trns[transparent_index as usize] = 0;
+                    png_encoder.set_trns(trns);
+                }
+                packed_indices.into()
+            }
+            PngPixels::Rgb(data) => {
+                png_encoder.set_color(png::ColorType::Rgb);
+                Cow::Borrowed(data)
+            }
+            PngPixels::Rgba(data) => {
+                png_encoder.set_color(png::ColorType::Rgba);
+                Cow::Borrowed(data)
+            }
+        };
+
+        if let Some(signature) = signature {
+            png_encoder
+                .add_text_chunk(PNG_SIGNATURE_KEYWORD.into(), format_signature(signature))?;
+        }
+
+        if let Some(palette_depth) = palette_depth {
+            let depth = match palette_depth {
+                PaletteDepth::SixBit => PNG_SIX_BIT_PALETTE,
+                PaletteDepth::EightBit => PNG_EIGHT_BIT_PALETTE,
+            };
+            png_encoder.add_text_chunk(PNG_PALETTE_DEPTH_KEYWORD.into(), depth.to_owned())?;
+        }
+
+        let mut png_writer = png_encoder.write_header()?;
+        png_writer.write_image_data(&data)?;
+        png_writer.finish()?;
+    }
+
+    Ok(png_data)
+}
+
+fn pack_indices(indices: &[u8], width: usize, bits_per_pixel: usize) -> Vec<u8> {
+    if bits_per_pixel == 8 {
+        return indices.to_vec();
+    }
+
+    let row_size = (width * bits_per_pixel).div_ceil(8);
+    let mut packed = Vec::with_capacity(row_size * indices.len() / width.max(1));
+
+    for row in indices.chunks_exact(width) {
+        let mut packed_row = vec![0u8; row_size];
+        for (x, &index) in row.iter().enumerate() {
+            let bit_offset = x * bits_per_pixel;
+            packed_row[bit_offset / 8] |= index << (8 - bits_per_pixel - bit_offset % 8);
+        }
+        packed.extend_from_slice(&packed_row);
+    }
+
+    packed
+}
+
+fn corner_color_key(indices: &[u8], width: usize, height: usize) -> Option<u8> {
+    if width == 0 || height == 0 {
+        return None;
+    }
+
+    let corners = [
+        indices[0],
+        indices[width - 1],
+        indices[width * (height - 1)],
+        indices[width * height - 1],
+    ];
+
+    corners
+        .iter()
+        .map(|&corner| (corners.iter().filter(|&&c| c == corner).count(), corner))
+        .filter(|&(count, _)| count > 1)
+        .max_by_key(|&(count, _)| count)
+        .map(|(_, corner)| corner)
+}
+
+/// Detects the palette index used as color key of a sprite: the index that is shared by most of
+/// the four corners of the image. `None` is returned if all corners differ.
+pub fn detect_color_key(image: &JonathanImage) -> Option<u8> {
+    corner_color_key(&image.indices, image.width as usize, image.height as usize)
+}
+
+/// Encodes `image` as PNG file of the matching color type: indexed PNG files with a bit depth of
+/// 1, 2, 4 or 8 for paletted images, RGB or RGBA PNG files for true color images.
+fn encode_pcx_image_png(
+    image: &PcxImage,
+    palette_depth: Option<PaletteDepth>,
+    transparent_color: TransparentColor,
+    transparency_output: TransparencyOutput,
+    warnings: &mut Vec<String>,
+) -> Result<Vec<u8>> {
+    match &image.pixels {
+        PcxPixels::Indexed {
+            bits_per_pixel,
+            indices,
+            palette,
+        } => {
+            let transparent_index = match transparent_color {
+                TransparentColor::None => None,
+                TransparentColor::Index(index) if (index as usize) < palette.len() => Some(index),
+                TransparentColor::Index(index) => {
+                    warnings.push(format!(
+                        "The transparent index {} is not part of the {} color palette.",
+                        index,
+                        palette.len()
+                    ));
+                    None
+                }
+                TransparentColor::AutoDetect => {
+                    let color_key =
+                        corner_color_key(indices, image.width as usize, image.height as usize);
+                    if color_key.is_none() {
+                        warnings.push(
+                            "No color key could be detected, the corners of the image differ."
+                                .to_owned(),
+                        );
+                    }
+                    color_key
+                }
+            };
+
+            if let (Some(transparent_index), TransparencyOutput::Rgba) =
+                (transparent_index, transparency_output)
+            {
+                let rgba: Vec<u8> = indices
+                    .iter()
+                    .flat_map(|&index| {
+                        let [r, g, b] = palette[index as usize];
+                        [r, g, b, if index == transparent_index { 0 } else { 0xff }]
+                    })
+                    .collect();
+
+                return write_png(
+                    image.width,
+                    image.height,
+                    image.signature,
+                    palette_depth,
+                    PngPixels::Rgba(&rgba),
+                );
+            }
+
+            let bit_depth = match bits_per_pixel {
+                1 => png::BitDepth::One,
+                2 => png::BitDepth::Two,
+                3..=4 => png::BitDepth::Four,
+                _ => png::BitDepth::Eight,
+            };
+
+            write_png(
+                image.width,
+                image.height,
+                image.signature,
+                palette_depth,
+                PngPixels::Indexed {
+                    bit_depth,
+                    palette,
+                    transparent_index,
+                    packed_indices: pack_indices(indices, image.width as usize, bit_depth as usize),
+                },
+            )
+        }
+        PcxPixels::Rgb(pixels) => write_png(
+            image.width,
+            image.height,
+            image.signature,
+            palette_depth,
+            PngPixels::Rgb(pixels),
+        ),
+        PcxPixels::Rgba(pixels) => write_png(
+            image.width,
+            image.height,
+            image.signature,
+            palette_depth,
+            PngPixels::Rgba(pixels),
+        ),
+    }
+}
+
+/// Encodes `image` as indexed PNG file, the signature and the palette depth are kept in text
+/// chunks.
+pub fn encode_png(image: &JonathanImage) -> Result<Vec<u8>> {
+    write_png(
+        image.width,
+        image.height,
+        image.signature,
+        Some(image.palette_depth),
+        PngPixels::Indexed {
+            bit_depth: png::BitDepth::Eight,
+            palette: &image.palette,
+            transparent_index: None,
+            packed_indices: image.indices.clone(),
+        },
+    )
+}
+
+/// Encodes RGB pixels as PNG file.
+pub(crate) fn encode_rgb_png(width: u32, height: u32, pixels: &[u8]) -> Result<Vec<u8>> {
+    write_png(width, height, None, None, PngPixels::Rgb(pixels))
+}
+
+/// Decodes an indexed PNG file with a bit depth of 8. The signature and the palette depth are
+/// restored from the text chunks written by the converter, if present.
+pub fn decode_png(data: &[u8]) -> Result<JonathanImage> {
+    read_png(data).map(|(image, _)| image)
+}
+
+/// Decodes an indexed PNG file like [`decode_png`] and returns the palette depth of its text chunk,
+/// `None` if the chunk is missing, e.g. because an image editor removed it.
+fn read_png(data: &[u8]) -> Result<(JonathanImage, Option<PaletteDepth>)> {
+    let mut decoder = png::Decoder::new(data);
+    decoder.set_transformations(png::Transformations::IDENTITY);
+    let mut reader = decoder
+        .read_info()
+        .wrap_err("Unable to read the data as PNG file.")?;
+
+    let (color_type, bit_depth) = reader.output_color_type();
+    if color_type != png::ColorType::Indexed || bit_depth != png::BitDepth::Eight {
+        bail!("The data is not an indexed PNG file with a bit depth of 8.");
+    }
+
+    let palette = {
+        let palette_data = match &reader.info().palette {
+            Some(palette_data) => palette_data,
+            None => bail!("The data does not contain a PNG palette."),
+        };
+
+        let mut palette = [[0u8; 3]; 256];
+        for (entry, rgb) in palette.iter_mut().zip(palette_data.chunks_exact(3)) {
+            entry.copy_from_slice(rgb);
+        }
+        palette
+    };
+
+    let text_chunk = |keyword: &str| {
+        reader
+            .info()
+            .uncompressed_latin1_text
+            .iter()
+            .find(|chunk| chunk.keyword == keyword)
+            .map(|chunk| chunk.text.clone())
+    };
+    let signature = text_chunk(PNG_SIGNATURE_KEYWORD).and_then(|text| parse_signature(&text));
+    let palette_depth = match text_chunk(PNG_PALETTE_DEPTH_KEYWORD).as_deref() {
+        Some(PNG_SIX_BIT_PALETTE) => Some(PaletteDepth::SixBit),
+        Some(PNG_EIGHT_BIT_PALETTE) => Some(PaletteDepth::EightBit),
+        _ => None,
+    };
+
+    let mut indices = vec![0u8; reader.output_buffer_size()];
+    let output_info = reader
+        .next_frame(&mut indices)
+        .wrap_err("Error occurred while decoding the image data.")?;
+    indices.truncate(output_info.buffer_size());
+
+    let image = JonathanImage {
+        width: output_info.width,
+        height: output_info.height,
+        indices,
+        palette,
+        signature,
+        palette_depth: palette_depth.unwrap_or(PaletteDepth::EightBit),
+    };
+    Ok((image, palette_depth))
+}
+
+/// Loads a PCX file of the game or a PNG file written by the converter, 6 bit VGA palettes of PCX
+/// files are detected and expanded like PNG files written by the converter.
+pub fn load_image(filename: &Path) -> Result<JonathanImage> {
+    load_image_with_mode(filename, PaletteMode::Auto)
+}
+
+/// Loads a PCX or PNG file like [`load_image`], the palettes of PCX files are interpreted
+/// according to `palette_mode`.
+pub fn load_image_with_mode(filename: &Path, palette_mode: PaletteMode) -> Result<JonathanImage> {
+    let contents = read_file_contents(filename)?;
+
+    if is_file_with_extension(filename, "PNG") {
+        decode_png(&contents)
+    } else {
+        decode_pcx(&contents).map(|mut image| {
+            image.palette_depth = normalize_palette(&mut image.palette, palette_mode);
+            image
+        })
+    }
+    .wrap_err_with(|| format!("Unable to decode '{}'.", filename.display()))
+}
+
+/// Writes `image` as PNG or PCX file depending on the extension of `filename`.
+pub fn save_image(filename: &Path, image: &JonathanImage) -> Result<()> {
+    let data = if is_file_with_extension(filename, "PNG") {
+        encode_png(image)
+    } else {
+        encode_pcx(image)
+    }
+    .wrap_err_with(|| format!("Unable to encode '{}'.", filename.display()))?;
+
+    create_output_file(filename)?
+        .write_all(&data)
+        .wrap_err_with(|| format!("Unable to write to '{}'.", filename.display()))
+}
+
+pub(crate) fn convert_pcx(
+    input_filename: &Path,
+    input_contents: &[u8],
+    output_filename: &Path,
+    options: &GraphicsOptions,
+    warnings: &mut Vec<String>,
+) -> Result<FileSummary> {
+    let decoded =
+        decode_pcx_with_mode(input_contents, options.decode_mode).wrap_err_with(|| {
+            format!(
+                "Unable to decode '{}' as PCX file.",
+                input_filename.display()
+            )
+        })?;
+    warnings.extend(decoded.warnings);
+    let mut image = decoded.image;
+
+    let (palette_depth, palette_hash) = match &mut image.pixels {
+        PcxPixels::Indexed { palette, .. } => (
+            Some(normalize_palette(palette, options.palette_mode)),
+            Some(palette_hash(palette)),
+        ),
+        _ => (None, None),
+    };
+
+    let png_data = encode_pcx_image_png(
+        &image,
+        palette_depth,
+        options.transparent_color,
+        options.transparency_output,
+        warnings,
+    )
+    .wrap_err_with(|| format!("Unable to encode '{}'.", output_filename.display()))?;
+
+    create_output_file(output_filename)?
+        .write_all(&png_data)
+        .wrap_err_with(|| format!("Unable to write to '{}'.", output_filename.display()))?;
+
+    Ok(FileSummary::Image {
+        width: image.width,
+        height: image.height,
+        palette_depth,
+        palette_hash,
+    })
+}
+
+pub(crate) fn convert_png(
+    input_filename: &Path,
+    input_contents: &[u8],
+    output_filename: &Path,
+    _warnings: &mut Vec<String>,
+) -> Result<FileSummary> {
+    let (mut image, palette_depth) = read_png(input_contents).wrap_err_with(|| {
+        format!(
+            "Unable to decode '{}' as PNG file.",
+            input_filename.display()
+        )
+    })?;
+
+    if image.signature.is_none() {
+        let original_contents = read_file_contents(output_filename).wrap_err_with(|| {
+            format!(
+                "'{}' does not contain the header bytes of the original PCX file, the original \
+                 '{}' is required to restore them.",
+                input_filename.display(),
+                output_filename.display()
+            )
+        })?;
+
+        image.signature = match original_contents.get(0..4) {
+            Some(signature) => Some([signature[0], signature[1], signature[2], signature[3]]),
+            None => bail!(
+                "'{}' is too small to be a valid PCX file.",
+                output_filename.display()
+            ),
+        };
+    }
+
+    // Without the text chunk the depth of the original palette is detected, so that a 6 bit
+    // palette is not written back with 8 bit color values.
+    if palette_depth.is_none() {
+        if let Some(original_palette) = read_file_contents(output_filename)
+            .ok()
+            .and_then(|contents| read_vga_palette(&contents))
+        {
+            image.palette_depth = detect_palette_depth(&original_palette);
+        }
+    }
+
+    let pcx_data = encode_pcx(&image)
+        .wrap_err_with(|| format!("Unable to encode '{}'.", output_filename.display()))?;
+
+    create_output_file(output_filename)?
+        .write_all(&pcx_data)
+        .wrap_err_with(|| format!("Unable to write to '{}'.", output_filename.display()))?;
+
+    Ok(FileSummary::Image {
+        width: image.width,
+        height: image.height,
+        palette_depth: None,
+        palette_hash: Some(palette_hash(&image.palette)),
+    })
+}
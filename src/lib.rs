@@ -1,308 +1,375 @@
-use eyre::bail;
-use eyre::Result;
-use eyre::WrapErr;
-use rayon::prelude::*;
-use std::{
-    fs::{read_dir, DirBuilder, File, OpenOptions},
-    io::{BufWriter, Read, Write},
-    path::{Path, PathBuf},
+mod archive;
+mod codec;
+mod converter;
+mod dialogue;
+mod fat;
+mod fs_util;
+mod gfx;
+mod manifest;
+mod palette;
+mod pcx;
+mod scene;
+mod source;
+mod state;
+mod text;
+
+use eyre::{bail, Result, WrapErr};
+use std::{io::Write, path::Path};
+
+pub use codec::{detect_encoding, CodecTable, TextEncoding};
+pub use converter::{
+    BatchReport, ConversionFailed, ConversionSummary, Converter, Direction, DirectoryLayout,
+    FileReport, FileSummary, ResourceKind,
+};
+pub use dialogue::{
+    export_dialogues, import_dialogues, Dialogue, DialogueEntry, DialogueFormat, DialogueItem,
+    DialogueReport,
+};
+pub use gfx::{
+    decode_png, detect_color_key, encode_png, load_image, load_image_with_mode, save_image,
+    JonathanImage, TransparencyOutput, TransparentColor,
+};
+pub use manifest::{Manifest, ManifestEntry, ManifestStatus};
+pub use palette::{
+    detect_palette_depth, expand_vga_palette, extract_palettes, format_act_palette,
+    format_gimp_palette, format_jasc_palette, normalize_palette, palette_hash, remap_image,
+    CatalogEntry, PaletteCatalog, PaletteDepth, PaletteMode, RemapMode,
+};
+pub use pcx::{
+    decode_pcx, decode_pcx_with_mode, encode_pcx, format_signature, DecodeMode, DecodedPcx,
+    PcxImage, PcxPixels,
+};
+pub use scene::{composite_scene, Overlay, Scene};
+pub use text::{
+    decode_tct, decode_tct_with_mode, decode_tct_with_table, encode_tct, encode_tct_with_mode,
+    encode_tct_with_table, escape_byte, DecodedText, LineEnding, TextFileEncoding, UnmappedByte,
+    UnmappedBytes,
 };
 
 // -------------------------------------------------------------------------------------------------
 
-const GFX_INPUT_DIR: &str = "GRAFIK";
-const GFX_OUTPUT_DIR: &str = "GRAFIK_PNG";
+fn print_reports(reports: &[FileReport]) {
+    for report in reports {
+        match &report.result {
+            Ok(FileSummary::UpToDate) => {
+                println!("'{}' is up to date.", report.output_path.display());
+                continue;
+            }
+            Ok(FileSummary::Removed) => {
+                println!(
+                    "Removing '{}', '{}' no longer exists.",
+                    report.output_path.display(),
+                    report.input_path.display()
+                );
+                continue;
+            }
+            _ => {}
+        }
 
-const TEXT_INPUT_DIR: &str = "TEXT";
-const TEXT_OUTPUT_DIR: &str = "TEXT_TXT";
+        println!(
+            "Converting '{}' to '{}' ...",
+            report.input_path.display(),
+            report.output_path.display()
+        );
 
-#[cfg(windows)]
-const LINE_ENDING: &str = "\r\n";
-#[cfg(not(windows))]
-const LINE_ENDING: &str = "\n";
+        if let Ok(FileSummary::Image {
+            palette_depth: Some(palette_depth),
+            ..
+        }) = &report.result
+        {
+            println!("Using the palette as {} color values.", palette_depth);
+        }
 
-// -------------------------------------------------------------------------------------------------
+        if let Ok(FileSummary::Text {
+            encoding: Some(encoding),
+            ..
+        }) = &report.result
+        {
+            println!("Detected the text encoding {}.", encoding);
+        }
 
-fn is_file_with_extension(path: &Path, extension_upper: &str) -> bool {
-    if path.is_file() {
-        path.extension().map_or(false, |e| {
-            e.to_str()
-                .map_or(false, |e| e.to_uppercase() == extension_upper)
-        })
-    } else {
-        false
+        for warning in &report.warnings {
+            println!("Warning: {}", warning);
+        }
+
+        if let Err(err) = &report.result {
+            println!("{:#}", err);
+        }
     }
 }
 
-fn create_output_file(path: &Path) -> Result<File> {
-    let file = OpenOptions::new()
-        .write(true)
-        .truncate(true)
-        .create(true)
-        .open(&path)
-        .wrap_err_with(|| {
-            format!(
-                "Unable to create '{}'. Is the path writable?",
-                path.display()
-            )
-        })?;
+pub fn run_converter(converter: &Converter) -> Result<()> {
+    let mut all_reports = Vec::new();
 
-    Ok(file)
-}
+    for (i, &kind) in converter.resource_kinds.iter().enumerate() {
+        if i > 0 {
+            println!();
+        }
 
-fn to_output_filename(
-    input_path: &Path,
-    output_path: &Path,
-    output_extension: &str,
-) -> Result<PathBuf> {
-    let stem = match input_path.file_stem() {
-        Some(stem) => stem,
-        None => bail!(
-            "Input path '{}' does not have a stem.",
-            input_path.display()
-        ),
-    };
+        println!("Converting {} ...", kind);
+        let mut reports = converter
+            .clone()
+            .resource_kinds(&[kind])
+            .without_summary_files()
+            .convert()?;
+        print_reports(&reports);
+        all_reports.append(&mut reports);
+    }
 
-    let mut output_filename = PathBuf::new();
-    output_filename.push(output_path);
-    output_filename.push(stem);
-    output_filename.set_extension(output_extension);
-    Ok(output_filename)
-}
+    let filenames = converter.write_summary_files(&all_reports)?;
+    println!();
+    for filename in filenames {
+        println!("Wrote '{}'.", filename.display());
+    }
+    println!("Summary: {}", ConversionSummary::from_reports(&all_reports));
 
-fn read_file_contents(filename: &Path) -> Result<Vec<u8>> {
-    let mut input_file = OpenOptions::new()
-        .read(true)
-        .open(filename)
-        .wrap_err_with(|| format!("Unable to open input file '{}'.", filename.display()))?;
-    let mut contents = Vec::new();
-    let _ = input_file
-        .read_to_end(&mut contents)
-        .wrap_err_with(|| format!("Unable to read input file '{}'.", filename.display()))?;
-    Ok(contents)
+    match ConversionFailed::from_reports(&all_reports) {
+        Some(failed) => Err(failed.into()),
+        None => Ok(()),
+    }
 }
 
-fn convert_pcx(input_filename: &Path, output_filename: &Path) -> Result<()> {
-    let input_file_contents = {
-        let mut input_file_contents = read_file_contents(input_filename)?;
+pub fn run_batch(converter: &Converter) -> Result<()> {
+    let batch_reports = converter.convert_batch()?;
+    if batch_reports.is_empty() {
+        bail!(
+            "No installation of the game was found in '{}'.",
+            converter.input_root.display()
+        );
+    }
 
-        if input_file_contents.len() < 4 {
-            bail!(
-                "'{}' is too small to be a valid PCX file.",
-                input_filename.display()
-            );
+    let mut summaries = Vec::new();
+    for batch_report in &batch_reports {
+        println!(
+            "Converting the installation in '{}' ...",
+            batch_report.installation.display()
+        );
+        match &batch_report.result {
+            Ok(reports) => {
+                print_reports(reports);
+                summaries.push(Some(ConversionSummary::from_reports(reports)));
+            }
+            Err(err) => {
+                println!("{:#}", err);
+                summaries.push(None);
+            }
         }
+        println!();
+    }
 
-        input_file_contents[0] = 0x0a;
-        input_file_contents[1] = 0x05;
-        input_file_contents[2] = 0x01;
-        input_file_contents[3] = 0x08;
-
-        input_file_contents
-    };
+    println!("Summary:");
+    let mut total = ConversionSummary::default();
+    for (batch_report, summary) in batch_reports.iter().zip(&summaries) {
+        match summary {
+            Some(summary) => {
+                println!("'{}': {}", batch_report.installation.display(), summary);
+                total.add(summary);
+            }
+            None => println!("'{}': failed", batch_report.installation.display()),
+        }
+    }
+    println!("{} installations: {}", batch_reports.len(), total);
+
+    let mut failed = Vec::new();
+    for batch_report in &batch_reports {
+        match &batch_report.result {
+            Ok(reports) => failed.extend(
+                ConversionFailed::from_reports(reports)
+                    .map(|failed| failed.failed)
+                    .unwrap_or_default(),
+            ),
+            Err(_) => failed.push(batch_report.installation.clone()),
+        }
+    }
 
-    let mut pcx_file = pcx::Reader::new(input_file_contents.as_slice()).wrap_err_with(|| {
-        format!(
-            "Unable to read contents of '{}' as PCX file.",
-            input_filename.display()
-        )
-    })?;
+    if failed.is_empty() {
+        Ok(())
+    } else {
+        let total = total.converted
+            + total.up_to_date
+            + total.removed
+            + total.failed
+            + summaries.iter().filter(|summary| summary.is_none()).count();
+        Err(ConversionFailed { failed, total }.into())
+    }
+}
 
-    if !pcx_file.is_paletted() || pcx_file.palette_length().unwrap_or(0) != 256 {
-        bail!(
-            "'{}' does not contain a 256 color PCX palette.",
-            input_filename.display()
+pub fn run_inspect(filenames: &[&Path]) -> Result<()> {
+    for filename in filenames {
+        let image = load_image(filename)?;
+        println!(
+            "'{}': {}x{} pixels, header bytes: {}",
+            filename.display(),
+            image.width,
+            image.height,
+            image
+                .signature
+                .map_or_else(|| "unknown".to_owned(), format_signature)
         );
     }
 
-    let width = pcx_file.width() as usize;
-    let height = pcx_file.height() as usize;
-
-    let image_data = {
-        let mut image_data = vec![0u8; width * height];
-        for y in 0..height {
-            let begin = width * y;
-            let end = begin + width;
-            pcx_file
-                .next_row_paletted(&mut image_data[begin..end])
-                .wrap_err_with(|| {
-                    format!(
-                        "Error occurred while decoding '{}'.",
-                        input_filename.display()
-                    )
-                })?;
-        }
-        image_data
-    };
-
-    let palette_data = {
-        let mut palette_data = vec![0u8; 256 * 3];
-        let _ = pcx_file.read_palette(&mut palette_data).wrap_err_with(|| {
-            format!(
-                "Error occurred while decoding palette of '{}'.",
-                input_filename.display()
-            )
-        })?;
-        palette_data
-    };
-
-    let writer = BufWriter::new(create_output_file(output_filename)?);
-    let mut png_encoder = png::Encoder::new(writer, width as u32, height as u32);
-    png_encoder.set_color(png::ColorType::Indexed);
-    png_encoder.set_depth(png::BitDepth::Eight);
-    png_encoder.set_palette(palette_data);
+    Ok(())
+}
 
-    let mut png_writer = png_encoder
-        .write_header()
-        .wrap_err_with(|| format!("Unable to write to '{}'.", output_filename.display()))?;
+pub fn run_palettes(
+    root_dir: &Path,
+    layout: &DirectoryLayout,
+    output_dir: &Path,
+    palette_mode: PaletteMode,
+) -> Result<()> {
+    let (source, game_dir) = source::open_game_source(root_dir, &[&layout.graphics_input])?;
+    let catalog = palette::extract_palettes_from_source(
+        source.as_ref(),
+        root_dir,
+        &source.find_dir_ignoring_case(&game_dir, &layout.graphics_input),
+        palette_mode,
+    )?;
+
+    for (filename, err) in &catalog.failures {
+        println!("Skipping '{}': {:#}", filename.display(), err);
+    }
 
-    png_writer
-        .write_image_data(&image_data)
-        .wrap_err_with(|| format!("Unable to write to '{}'.", output_filename.display()))?;
+    catalog.write(output_dir)?;
+    print!("{}", catalog.report());
+    println!(
+        "Wrote {} distinct palettes to '{}'.",
+        catalog.entries.len(),
+        output_dir.display()
+    );
 
     Ok(())
 }
 
-fn convert_dir(
-    input_path: &Path,
-    input_extension: &str,
-    output_path: &Path,
-    output_extension: &str,
-    conversion_fn: &(dyn Fn(&Path, &Path) -> Result<()> + Sync),
+pub fn run_dialogues(
+    root_dir: &Path,
+    layout: &DirectoryLayout,
+    dialogue_dir: &Path,
+    format: DialogueFormat,
+    import: bool,
+    table: &CodecTable,
 ) -> Result<()> {
-    let dir_reader = read_dir(&input_path).wrap_err_with(|| {
-        format!(
-            "Unable to read directory '{}'. Is the provided path correct?",
-            input_path.display()
-        )
-    })?;
-
-    let _ = DirBuilder::new().create(&output_path);
-
-    let files_to_convert = {
-        let mut files_to_convert = Vec::new();
-
-        for entry in dir_reader {
-            let entry = entry.wrap_err_with(|| {
-                format!(
-                    "Unable to read directory entry in '{}'.",
-                    input_path.display()
-                )
-            })?;
-            let input_filename = entry.path();
-            if is_file_with_extension(&input_filename, input_extension) {
-                let output_filename =
-                    to_output_filename(&input_filename, output_path, output_extension)
-                        .wrap_err_with(|| {
-                            format!(
-                                "Unable to create output filename for input file '{}'.",
-                                input_filename.display()
-                            )
-                        })?;
-
-                files_to_convert.push((input_filename.to_owned(), output_filename));
-            }
+    let report = if import {
+        if root_dir.is_file() {
+            bail!(
+                "Dialogues cannot be imported into '{}', only into a directory.",
+                root_dir.display()
+            );
         }
-
-        files_to_convert
+        let text_dir = fs_util::find_dir_ignoring_case(root_dir, &layout.text_input);
+        import_dialogues(dialogue_dir, &text_dir, format, table)?
+    } else {
+        let (source, game_dir) = source::open_game_source(root_dir, &[&layout.text_input])?;
+        dialogue::export_dialogues_from_source(
+            source.as_ref(),
+            root_dir,
+            &source.find_dir_ignoring_case(&game_dir, &layout.text_input),
+            dialogue_dir,
+            format,
+            table,
+        )?
     };
 
-    files_to_convert
-        .par_iter()
-        .for_each(|(input_filename, output_filename)| {
-            println!(
-                "Converting '{}' to '{}' ...",
-                input_filename.display(),
-                output_filename.display()
-            );
-
-            let conversion_result =
-                conversion_fn(input_filename, output_filename).wrap_err_with(|| {
-                    format!(
-                        "Unable to convert '{}' to '{}'.",
-                        input_filename.display(),
-                        output_filename.display()
-                    )
-                });
+    for (filename, output_filename) in &report.converted {
+        println!(
+            "Converted '{}' to '{}'.",
+            filename.display(),
+            output_filename.display()
+        );
+    }
+    for (filename, err) in &report.failures {
+        println!("Skipping '{}': {:#}", filename.display(), err);
+    }
 
-            if let Err(err) = conversion_result {
-                println!("{:#}", err);
-            }
-        });
+    if !report.failures.is_empty() {
+        bail!(
+            "{} of {} dialogues could not be converted.",
+            report.failures.len(),
+            report.failures.len() + report.converted.len()
+        );
+    }
+    println!("Converted {} dialogues.", report.converted.len());
 
     Ok(())
 }
 
-fn convert_graphics(root_dir: &str) -> Result<()> {
-    let gfx_input_path: PathBuf = [root_dir, GFX_INPUT_DIR].iter().collect();
-    let gfx_output_path: PathBuf = [root_dir, GFX_OUTPUT_DIR].iter().collect();
-
-    convert_dir(
-        &gfx_input_path,
-        "PCX",
-        &gfx_output_path,
-        "PNG",
-        &convert_pcx,
-    )
-}
-
-fn convert_txt(input_filename: &Path, output_filename: &Path) -> Result<()> {
-    let file_contents = read_file_contents(input_filename)?;
-
-    let converted_file_contents = {
-        let mut s = String::with_capacity(file_contents.len());
-        s.push('\u{feff}');
-        for &c in &file_contents {
-            match c {
-                10 => s.push_str(LINE_ENDING),
-                11..=136 => s.push((c - 10) as char),
-                139 => s.push('ü'),
-                164 => s.push('Ü'),
-                142 => s.push('ä'),
-                152 => s.push('Ä'),
-                158 => s.push('ö'),
-                163 => s.push('Ö'),
-                183 => s.push('ô'),
-                235 => s.push('ß'),
-                _ => bail!(
-                    "'{}' contains illegal character {}",
-                    input_filename.display(),
-                    c
-                ),
-            };
-        }
+pub fn run_remap(
+    input_filename: &Path,
+    reference_filename: &Path,
+    output_filename: &Path,
+    mode: RemapMode,
+    color_key: TransparentColor,
+    palette_mode: PaletteMode,
+) -> Result<()> {
+    let image = load_image_with_mode(input_filename, palette_mode)?;
+    let reference = load_image_with_mode(reference_filename, palette_mode)?;
 
-        s
+    let transparent_index = match color_key {
+        TransparentColor::None => None,
+        TransparentColor::Index(index) => Some(index),
+        TransparentColor::AutoDetect => detect_color_key(&image),
     };
 
-    let mut file = create_output_file(output_filename)?;
-    file.write_all(converted_file_contents.as_bytes())
-        .wrap_err_with(|| format!("Unable to write to '{}'.", output_filename.display()))?;
+    let remapped =
+        remap_image(&image, &reference, mode, transparent_index).wrap_err_with(|| {
+            format!(
+                "Unable to remap '{}' onto the palette of '{}'.",
+                input_filename.display(),
+                reference_filename.display()
+            )
+        })?;
+    save_image(output_filename, &remapped)?;
+
+    println!(
+        "Remapped '{}' onto the palette of '{}' and wrote '{}'.",
+        input_filename.display(),
+        reference_filename.display(),
+        output_filename.display()
+    );
 
     Ok(())
 }
 
-fn convert_texts(root_dir: &str) -> Result<()> {
-    let txt_input_path: PathBuf = [root_dir, TEXT_INPUT_DIR].iter().collect();
-    let txt_output_path: PathBuf = [root_dir, TEXT_OUTPUT_DIR].iter().collect();
-
-    convert_dir(
-        &txt_input_path,
-        "TCT",
-        &txt_output_path,
-        "TXT",
-        &convert_txt,
-    )
-}
+pub fn run_composite(
+    background_filename: &Path,
+    overlays: &[(&Path, i32, i32)],
+    color_key: TransparentColor,
+    palette_mode: PaletteMode,
+    output_filename: &Path,
+) -> Result<()> {
+    let background = load_image_with_mode(background_filename, palette_mode)?;
+    let overlays = overlays
+        .iter()
+        .map(|&(filename, x, y)| {
+            Ok(Overlay {
+                image: load_image_with_mode(filename, palette_mode)?,
+                x,
+                y,
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
 
-pub fn run(root_dir: &str) -> Result<()> {
-    println!("Converting graphics ...");
-    convert_graphics(root_dir)?;
+    let mut warnings = Vec::new();
+    let scene = composite_scene(&background, &overlays, color_key, &mut warnings);
+    for warning in &warnings {
+        println!("Warning: {}", warning);
+    }
 
-    println!();
+    let png_data = scene
+        .encode_png()
+        .wrap_err_with(|| format!("Unable to encode '{}'.", output_filename.display()))?;
+    fs_util::create_output_file(output_filename)?
+        .write_all(&png_data)
+        .wrap_err_with(|| format!("Unable to write to '{}'.", output_filename.display()))?;
 
-    println!("Converting texts ...");
-    convert_texts(root_dir)?;
+    println!(
+        "Wrote the scene with {} overlays to '{}'.",
+        overlays.len(),
+        output_filename.display()
+    );
 
     Ok(())
 }
+
+pub fn run(root_dir: &str) -> Result<()> {
+    run_converter(&Converter::new(root_dir))
+}
@@ -1,5 +1,10 @@
-use clap::{Arg, Command};
-use std::io::prelude::*;
+use clap::{Arg, ArgAction, ArgMatches, Command};
+use eyre::Result;
+use jonathan_converter::{
+    CodecTable, Converter, DecodeMode, DialogueFormat, Direction, DirectoryLayout, LineEnding,
+    PaletteMode, RemapMode, TextFileEncoding, TransparencyOutput, TransparentColor, UnmappedBytes,
+};
+use std::{io::prelude::*, path::Path};
 
 fn pause() {
     let mut stdin = std::io::stdin();
@@ -11,14 +16,496 @@ fn pause() {
     let _ = stdin.read(&mut [0u8]).unwrap();
 }
 
+fn parse_transparent_color(value: &str) -> Result<TransparentColor, String> {
+    if value.eq_ignore_ascii_case("auto") {
+        Ok(TransparentColor::AutoDetect)
+    } else {
+        value
+            .parse()
+            .map(TransparentColor::Index)
+            .map_err(|_| "Expected a palette index from 0 to 255 or 'auto'.".to_owned())
+    }
+}
+
+fn parse_color_key(value: &str) -> Result<TransparentColor, String> {
+    if value.eq_ignore_ascii_case("none") {
+        Ok(TransparentColor::None)
+    } else {
+        parse_transparent_color(value)
+    }
+}
+
+fn parse_palette_mode(value: &str) -> Result<PaletteMode, String> {
+    match value {
+        "auto" => Ok(PaletteMode::Auto),
+        "8" => Ok(PaletteMode::EightBit),
+        "6" => Ok(PaletteMode::SixBit),
+        _ => Err("Expected 'auto', '8' or '6'.".to_owned()),
+    }
+}
+
+fn parse_overlay(value: &str) -> Result<(String, i32, i32), String> {
+    let Some((filename, position)) = value.rsplit_once('@') else {
+        return Ok((value.to_owned(), 0, 0));
+    };
+
+    position
+        .split_once(',')
+        .and_then(|(x, y)| Some((filename.to_owned(), x.parse().ok()?, y.parse().ok()?)))
+        .ok_or_else(|| "Expected a filename optionally followed by '@X,Y'.".to_owned())
+}
+
+fn parse_unmapped_bytes(value: &str) -> Result<UnmappedBytes, String> {
+    match value {
+        "reject" => Ok(UnmappedBytes::Reject),
+        "escape" => Ok(UnmappedBytes::Escape),
+        "private-use" => Ok(UnmappedBytes::PrivateUse),
+        _ => Err("Expected 'reject', 'escape' or 'private-use'.".to_owned()),
+    }
+}
+
+fn parse_line_ending(value: &str) -> Result<LineEnding, String> {
+    match value {
+        "lf" => Ok(LineEnding::Lf),
+        "crlf" => Ok(LineEnding::CrLf),
+        _ => Err("Expected 'lf' or 'crlf'.".to_owned()),
+    }
+}
+
+fn parse_text_file_encoding(value: &str) -> Result<TextFileEncoding, String> {
+    match value {
+        "utf-8" => Ok(TextFileEncoding::Utf8),
+        "utf-16" => Ok(TextFileEncoding::Utf16),
+        "latin-1" => Ok(TextFileEncoding::Latin1),
+        _ => Err("Expected 'utf-8', 'utf-16' or 'latin-1'.".to_owned()),
+    }
+}
+
+fn load_codec_table(matches: &ArgMatches) -> Result<CodecTable> {
+    let table = match matches.get_one::<String>("codec-table") {
+        Some(filename) => CodecTable::load(Path::new(filename))?,
+        None => CodecTable::default(),
+    };
+
+    Ok(match matches.get_one::<i16>("shift") {
+        Some(&shift) if shift != 0 => table.shifted(shift),
+        _ => table,
+    })
+}
+
+fn convert(matches: &ArgMatches) -> Result<()> {
+    let converter = {
+        let converter = Converter::new(
+            matches
+                .get_one::<String>("DIRECTORY")
+                .map_or(".", String::as_str),
+        );
+
+        let converter = match matches.get_one::<String>("output") {
+            Some(output_root) => converter.output_root(output_root),
+            None => converter,
+        };
+
+        let converter = {
+            let name = |id: &str| matches.get_one::<String>(id).unwrap().clone();
+            converter.directory_layout(DirectoryLayout {
+                graphics_input: name("graphics-dir"),
+                graphics_output: name("graphics-output-dir"),
+                text_input: name("text-dir"),
+                text_output: name("text-output-dir"),
+            })
+        };
+
+        let converter = if matches.get_flag("import") {
+            converter.direction(Direction::Import)
+        } else {
+            converter
+        };
+
+        let converter = if matches.get_flag("lenient") {
+            converter.decode_mode(DecodeMode::Lenient {
+                fill_index: *matches.get_one::<u8>("fill-index").unwrap(),
+            })
+        } else {
+            converter
+        };
+
+        let converter = match matches.get_one::<TransparentColor>("transparent") {
+            Some(&transparent_color) => converter.transparent_color(transparent_color),
+            None => converter,
+        };
+
+        let converter = if matches.get_flag("rgba") {
+            converter.transparency_output(TransparencyOutput::Rgba)
+        } else {
+            converter
+        };
+
+        let converter = converter
+            .palette_mode(*matches.get_one::<PaletteMode>("palette").unwrap())
+            .write_manifest(matches.get_flag("manifest"))
+            .incremental(matches.get_flag("incremental"))
+            .unmapped_bytes(*matches.get_one::<UnmappedBytes>("unmapped").unwrap());
+
+        let converter = converter
+            .codec_table(load_codec_table(matches)?)
+            .detect_encoding(matches.get_flag("detect-encoding"))
+            .byte_order_mark(!matches.get_flag("no-bom"))
+            .text_file_encoding(
+                *matches
+                    .get_one::<TextFileEncoding>("text-file-encoding")
+                    .unwrap(),
+            );
+
+        let converter = match matches.get_one::<LineEnding>("line-ending") {
+            Some(&line_ending) => converter.line_ending(line_ending),
+            None => converter,
+        };
+
+        match matches.get_one::<String>("zip") {
+            Some(filename) => converter.output_archive(filename),
+            None => converter,
+        }
+    };
+
+    if matches.get_flag("batch") {
+        jonathan_converter::run_batch(&converter)
+    } else {
+        jonathan_converter::run_converter(&converter)
+    }
+}
+
+fn inspect(matches: &ArgMatches) -> Result<()> {
+    let filenames: Vec<&Path> = matches
+        .get_many::<String>("FILES")
+        .unwrap_or_default()
+        .map(Path::new)
+        .collect();
+
+    jonathan_converter::run_inspect(&filenames)
+}
+
+fn palettes(matches: &ArgMatches) -> Result<()> {
+    let root_dir = Path::new(
+        matches
+            .get_one::<String>("DIRECTORY")
+            .map_or(".", String::as_str),
+    );
+    let output_dir = matches
+        .get_one::<String>("output")
+        .map_or_else(|| root_dir.join("PALETTES"), Into::into);
+
+    let layout = DirectoryLayout {
+        graphics_input: matches.get_one::<String>("graphics-dir").unwrap().clone(),
+        ..DirectoryLayout::default()
+    };
+
+    jonathan_converter::run_palettes(
+        root_dir,
+        &layout,
+        &output_dir,
+        *matches.get_one::<PaletteMode>("palette").unwrap(),
+    )
+}
+
+fn dialogues(matches: &ArgMatches) -> Result<()> {
+    let root_dir = Path::new(
+        matches
+            .get_one::<String>("DIRECTORY")
+            .map_or(".", String::as_str),
+    );
+    let dialogue_dir = matches
+        .get_one::<String>("dialogue-dir")
+        .map_or_else(|| root_dir.join("DIALOGUES"), Into::into);
+    let table = load_codec_table(matches)?;
+
+    let layout = DirectoryLayout {
+        text_input: matches.get_one::<String>("text-dir").unwrap().clone(),
+        ..DirectoryLayout::default()
+    };
+
+    jonathan_converter::run_dialogues(
+        root_dir,
+        &layout,
+        &dialogue_dir,
+        *matches.get_one::<DialogueFormat>("format").unwrap(),
+        matches.get_flag("import"),
+        &table,
+    )
+}
+
+fn remap(matches: &ArgMatches) -> Result<()> {
+    let filename = |id: &str| Path::new(matches.get_one::<String>(id).unwrap());
+
+    jonathan_converter::run_remap(
+        filename("INPUT"),
+        filename("REFERENCE"),
+        filename("OUTPUT"),
+        if matches.get_flag("exact") {
+            RemapMode::Exact
+        } else {
+            RemapMode::Nearest
+        },
+        *matches.get_one::<TransparentColor>("transparent").unwrap(),
+        *matches.get_one::<PaletteMode>("palette").unwrap(),
+    )
+}
+
+fn composite(matches: &ArgMatches) -> Result<()> {
+    let overlays: Vec<(&Path, i32, i32)> = matches
+        .get_many::<(String, i32, i32)>("OVERLAYS")
+        .unwrap_or_default()
+        .map(|(filename, x, y)| (Path::new(filename), *x, *y))
+        .collect();
+
+    jonathan_converter::run_composite(
+        Path::new(matches.get_one::<String>("BACKGROUND").unwrap()),
+        &overlays,
+        *matches.get_one::<TransparentColor>("transparent").unwrap(),
+        *matches.get_one::<PaletteMode>("palette").unwrap(),
+        Path::new(matches.get_one::<String>("output").unwrap()),
+    )
+}
+
 fn main() {
     let matches = Command::new(env!("CARGO_PKG_NAME"))
         .author(env!("CARGO_PKG_AUTHORS"))
         .version(env!("CARGO_PKG_VERSION"))
         .about(env!("CARGO_PKG_DESCRIPTION"))
         .arg(Arg::new("DIRECTORY")
-            .help("The root directory of the 'Jonathan' game. By default the current directory is used.")
+            .help("The root directory of the 'Jonathan' game, a ZIP archive or a FAT disk image (.img, .ima) containing it. By default the current directory is used.")
             .index(1))
+        .arg(Arg::new("output")
+            .long("output")
+            .value_name("DIRECTORY")
+            .help("Writes the output directories into the given directory instead of the game directory, e.g. for games on read-only media."))
+        .arg(Arg::new("graphics-dir")
+            .long("graphics-dir")
+            .value_name("NAME")
+            .help("The name of the directory with the game's PCX files.")
+            .default_value("GRAFIK"))
+        .arg(Arg::new("graphics-output-dir")
+            .long("graphics-output-dir")
+            .value_name("NAME")
+            .help("The name of the directory with the PNG files.")
+            .default_value("GRAFIK_PNG"))
+        .arg(Arg::new("text-dir")
+            .long("text-dir")
+            .value_name("NAME")
+            .help("The name of the directory with the game's TCT files.")
+            .default_value("TEXT"))
+        .arg(Arg::new("text-output-dir")
+            .long("text-output-dir")
+            .value_name("NAME")
+            .help("The name of the directory with the text files.")
+            .default_value("TEXT_TXT"))
+        .arg(Arg::new("batch")
+            .long("batch")
+            .action(ArgAction::SetTrue)
+            .help("Converts every installation of the game found in DIRECTORY and its subdirectories. With --output the outputs are written into a mirrored directory tree."))
+        .arg(Arg::new("import")
+            .long("import")
+            .action(ArgAction::SetTrue)
+            .help("Converts the edited files in the GRAFIK_PNG and TEXT_TXT directories back to the game's formats."))
+        .arg(Arg::new("lenient")
+            .long("lenient")
+            .action(ArgAction::SetTrue)
+            .help("Decodes as much as possible of damaged PCX files instead of skipping them."))
+        .arg(Arg::new("fill-index")
+            .long("fill-index")
+            .value_name("INDEX")
+            .value_parser(clap::value_parser!(u8))
+            .default_value("0")
+            .requires("lenient")
+            .help("The palette index used for the rows of damaged PCX files that could not be decoded."))
+        .arg(Arg::new("transparent")
+            .long("transparent")
+            .value_name("INDEX|auto")
+            .value_parser(parse_transparent_color)
+            .help("Makes the given palette index transparent in the PNG files. With 'auto' the color key is detected from the corners of each image."))
+        .arg(Arg::new("rgba")
+            .long("rgba")
+            .action(ArgAction::SetTrue)
+            .requires("transparent")
+            .help("Writes transparent images as RGBA PNG files instead of indexed PNG files with a tRNS chunk."))
+        .arg(Arg::new("palette")
+            .long("palette")
+            .value_name("auto|8|6")
+            .value_parser(parse_palette_mode)
+            .default_value("auto")
+            .help("The depth of the color values of the PCX palettes. With 'auto' 6 bit VGA palettes are detected and expanded to 8 bit."))
+        .arg(Arg::new("zip")
+            .long("zip")
+            .value_name("FILE")
+            .conflicts_with("batch")
+            .help("Additionally writes all converted files and a manifest into a reproducible ZIP archive."))
+        .arg(Arg::new("incremental")
+            .long("incremental")
+            .action(ArgAction::SetTrue)
+            .help("Only converts the files that changed since the last incremental conversion and removes the outputs of deleted files."))
+        .arg(Arg::new("manifest")
+            .long("manifest")
+            .action(ArgAction::SetTrue)
+            .help("Writes a manifest.json describing every converted file next to the output directories."))
+        .arg(Arg::new("unmapped")
+            .long("unmapped")
+            .value_name("reject|escape|private-use")
+            .value_parser(parse_unmapped_bytes)
+            .default_value("reject")
+            .help("How bytes of TCT files without a character are handled. With 'escape' they are written as \\x{C8}, with 'private-use' as the characters U+E000 to U+E0FF. Both are converted back to the original bytes by --import with the same mode."))
+        .arg(Arg::new("codec-table")
+            .long("codec-table")
+            .value_name("FILE")
+            .help("Reads the mapping between the bytes of TCT files and characters from the given table file instead of using the table of the German release."))
+        .arg(Arg::new("shift")
+            .long("shift")
+            .value_name("N")
+            .value_parser(clap::value_parser!(i16).range(-255..=255))
+            .allow_negative_numbers(true)
+            .conflicts_with("detect-encoding")
+            .help("Moves the bytes of the codec table by N, e.g. to import the texts of a release detected as 'german' shifted by -3 with --import --shift -3."))
+        .arg(Arg::new("detect-encoding")
+            .long("detect-encoding")
+            .action(ArgAction::SetTrue)
+            .help("Detects the variant of the text encoding of every TCT file by trying the codec tables with their bytes shifted and reports it. Other releases of the game may use other byte offsets."))
+        .arg(Arg::new("line-ending")
+            .long("line-ending")
+            .value_name("lf|crlf")
+            .value_parser(parse_line_ending)
+            .help("The line ending of the text files. By default the line ending of the operating system is used."))
+        .arg(Arg::new("no-bom")
+            .long("no-bom")
+            .action(ArgAction::SetTrue)
+            .help("Writes the text files without a byte order mark."))
+        .arg(Arg::new("text-file-encoding")
+            .long("text-file-encoding")
+            .value_name("utf-8|utf-16|latin-1")
+            .value_parser(parse_text_file_encoding)
+            .default_value("utf-8")
+            .help("The character encoding of the text files. UTF-16 files are written in little endian byte order."))
+        .subcommand(Command::new("inspect")
+            .about("Shows the dimensions and the original header bytes of PCX files and PNG files written by the converter.")
+            .arg(Arg::new("FILES")
+                .required(true)
+                .num_args(1..)))
+        .subcommand(Command::new("palettes")
+            .about("Extracts the distinct palettes of the PCX files as .pal, .gpl and .act files and lists the images that share them.")
+            .arg(Arg::new("DIRECTORY")
+                .help("The root directory of the 'Jonathan' game. By default the current directory is used.")
+                .index(1))
+            .arg(Arg::new("output")
+                .long("output")
+                .value_name("DIRECTORY")
+                .help("The directory the palette files are written to. By default the PALETTES directory in the game directory is used."))
+            .arg(Arg::new("graphics-dir")
+                .long("graphics-dir")
+                .value_name("NAME")
+                .help("The name of the directory with the game's PCX files.")
+                .default_value("GRAFIK"))
+            .arg(Arg::new("palette")
+                .long("palette")
+                .value_name("auto|8|6")
+                .value_parser(parse_palette_mode)
+                .default_value("auto")
+                .help("The depth of the color values of the PCX palettes.")))
+        .subcommand(Command::new("dialogues")
+            .about("Writes the speakers, lines and choices of the dialogue files D<n>.TCT as JSON or YAML files, and converts the edited files back with --import.")
+            .arg(Arg::new("DIRECTORY")
+                .help("The root directory of the 'Jonathan' game. By default the current directory is used.")
+                .index(1))
+            .arg(Arg::new("dialogue-dir")
+                .long("dialogue-dir")
+                .value_name("DIRECTORY")
+                .help("The directory of the JSON or YAML files. By default the DIALOGUES directory in the game directory is used."))
+            .arg(Arg::new("text-dir")
+                .long("text-dir")
+                .value_name("NAME")
+                .help("The name of the directory with the game's TCT files.")
+                .default_value("TEXT"))
+            .arg(Arg::new("format")
+                .long("format")
+                .value_name("json|yaml")
+                .value_parser(|value: &str| match value {
+                    "json" => Ok(DialogueFormat::Json),
+                    "yaml" => Ok(DialogueFormat::Yaml),
+                    _ => Err("Expected 'json' or 'yaml'.".to_owned()),
+                })
+                .default_value("json")
+                .help("The format of the dialogue files."))
+            .arg(Arg::new("import")
+                .long("import")
+                .action(ArgAction::SetTrue)
+                .help("Converts the JSON or YAML files back to the game's TCT files."))
+            .arg(Arg::new("codec-table")
+                .long("codec-table")
+                .value_name("FILE")
+                .help("Reads the mapping between the bytes of TCT files and characters from the given table file."))
+            .arg(Arg::new("shift")
+                .long("shift")
+                .value_name("N")
+                .value_parser(clap::value_parser!(i16).range(-255..=255))
+                .allow_negative_numbers(true)
+                .help("Moves the bytes of the codec table by N.")))
+        .subcommand(Command::new("remap")
+            .about("Re-indexes the pixels of an image onto the palette of a reference image, e.g. a sprite onto the palette of its scene.")
+            .arg(Arg::new("INPUT")
+                .help("The PCX or PNG file to remap.")
+                .required(true)
+                .index(1))
+            .arg(Arg::new("REFERENCE")
+                .help("The PCX or PNG file whose palette is used.")
+                .required(true)
+                .index(2))
+            .arg(Arg::new("OUTPUT")
+                .help("The PCX or PNG file to write.")
+                .required(true)
+                .index(3))
+            .arg(Arg::new("exact")
+                .long("exact")
+                .action(ArgAction::SetTrue)
+                .help("Fails if a color is not part of the reference palette instead of using the closest color."))
+            .arg(Arg::new("transparent")
+                .long("transparent")
+                .value_name("INDEX|auto|none")
+                .value_parser(parse_color_key)
+                .default_value("none")
+                .help("The color key of the input image, which keeps its index and is never used for other colors. With 'auto' the color key is detected from the corners of the image."))
+            .arg(Arg::new("palette")
+                .long("palette")
+                .value_name("auto|8|6")
+                .value_parser(parse_palette_mode)
+                .default_value("auto")
+                .help("The depth of the color values of the PCX palettes.")))
+        .subcommand(Command::new("composite")
+            .about("Draws overlays like the G<n>_<k> sprites on top of a background like ORT<n> and writes the scene as RGB PNG file.")
+            .arg(Arg::new("BACKGROUND")
+                .help("The PCX or PNG file of the background.")
+                .required(true)
+                .index(1))
+            .arg(Arg::new("OVERLAYS")
+                .help("The PCX or PNG files drawn on top of the background in the given order. The position of an overlay is given as FILE@X,Y, by default it is drawn at 0,0.")
+                .value_parser(parse_overlay)
+                .required(true)
+                .num_args(1..)
+                .index(2))
+            .arg(Arg::new("output")
+                .long("output")
+                .value_name("FILE")
+                .required(true)
+                .help("The PNG file the scene is written to."))
+            .arg(Arg::new("transparent")
+                .long("transparent")
+                .value_name("INDEX|auto|none")
+                .value_parser(parse_color_key)
+                .default_value("auto")
+                .help("The color key of the overlays. With 'auto' the color key is detected from the corners of each overlay."))
+            .arg(Arg::new("palette")
+                .long("palette")
+                .value_name("auto|8|6")
+                .value_parser(parse_palette_mode)
+                .default_value("auto")
+                .help("The depth of the color values of the PCX palettes.")))
+        .args_conflicts_with_subcommands(true)
         .after_help("The PCX files in the GRAFIK directory are converted to PNG files and written to the new directory GRAFIK_PNG.\n\
                      The TCT files in the TEXT directory are converted to UTF-8 text files and written to the new directory TEXT_TXT.")
         .get_matches();
@@ -31,13 +518,22 @@ fn main() {
     println!(env!("CARGO_PKG_AUTHORS"));
     println!();
 
-    if let Err(ref err) =
-        jonathan_converter::run(matches.get_one::<String>("DIRECTORY").unwrap_or(&".".to_string()))
-    {
+    let (result, interactive) = match matches.subcommand() {
+        Some(("inspect", matches)) => (inspect(matches), false),
+        Some(("palettes", matches)) => (palettes(matches), false),
+        Some(("dialogues", matches)) => (dialogues(matches), false),
+        Some(("remap", matches)) => (remap(matches), false),
+        Some(("composite", matches)) => (composite(matches), false),
+        _ => (convert(&matches), true),
+    };
+
+    if let Err(ref err) = result {
         eprintln!("{err:#}");
-        pause();
+        if interactive {
+            pause();
+        }
         std::process::exit(1);
-    } else {
+    } else if interactive {
         pause();
     }
 }
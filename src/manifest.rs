@@ -0,0 +1,208 @@
+use crate::{
+    codec::TextEncoding,
+    converter::{Direction, FileReport, FileSummary, ResourceKind},
+    fs_util::{create_output_file, read_file_contents, relative_path, sha256_hex},
+    palette::PaletteDepth,
+};
+use eyre::{Result, WrapErr};
+use serde::Serialize;
+use std::{
+    io::Write,
+    path::{Path, PathBuf, MAIN_SEPARATOR},
+};
+
+// -------------------------------------------------------------------------------------------------
+
+pub(crate) const MANIFEST_FILENAME: &str = "manifest.json";
+
+// -------------------------------------------------------------------------------------------------
+
+/// A machine-readable description of a conversion, written as `manifest.json` into the output
+/// directory.
+#[derive(Clone, Debug, Serialize)]
+pub struct Manifest {
+    /// The version of the converter that wrote the manifest.
+    pub converter_version: String,
+    pub direction: Direction,
+    /// The roots are left out of the manifests in output archives to keep them reproducible.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub input_root: Option<PathBuf>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub output_root: Option<PathBuf>,
+    pub files: Vec<ManifestEntry>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ManifestStatus {
+    Converted,
+    Failed,
+    /// The output was kept from an earlier incremental conversion.
+    UpToDate,
+    /// The output was deleted because its source no longer exists.
+    Removed,
+}
+
+/// The description of a single converted file. The paths are relative to the input and output
+/// roots of the manifest and use `/` as separator.
+#[derive(Clone, Debug, Serialize)]
+pub struct ManifestEntry {
+    pub kind: ResourceKind,
+    pub source: String,
+    pub output: String,
+    pub status: ManifestStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub width: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub height: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub palette_depth: Option<PaletteDepth>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub palette_sha256: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub characters: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub encoding: Option<TextEncoding>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub input_sha256: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub output_sha256: Option<String>,
+}
+
+fn file_hash(path: &Path) -> Option<String> {
+    read_file_contents(path)
+        .ok()
+        .map(|contents| sha256_hex(&contents))
+}
+
+impl Manifest {
+    /// Describes the given reports, the input and output files are hashed as they are on disk.
+    pub fn new(
+        direction: Direction,
+        input_root: &Path,
+        output_root: &Path,
+        reports: &[FileReport],
+    ) -> Self {
+        let files = reports
+            .iter()
+            .map(|report| {
+                let mut entry = ManifestEntry {
+                    kind: report.kind,
+                    source: relative_path(&report.input_path, input_root),
+                    output: relative_path(&report.output_path, output_root),
+                    status: ManifestStatus::Converted,
+                    error: None,
+                    warnings: report.warnings.clone(),
+                    width: None,
+                    height: None,
+                    palette_depth: None,
+                    palette_sha256: None,
+                    characters: None,
+                    encoding: None,
+                    input_sha256: report.input_hash.clone(),
+                    output_sha256: None,
+                };
+
+                // Up to date outputs are described by the conversion that wrote them.
+                let summary = match &report.result {
+                    Ok(FileSummary::UpToDate) => {
+                        entry.status = ManifestStatus::UpToDate;
+                        report.previous_summary.as_ref()
+                    }
+                    Ok(summary) => Some(summary),
+                    Err(err) => {
+                        entry.status = ManifestStatus::Failed;
+                        entry.error = Some(format!("{:#}", err));
+                        None
+                    }
+                };
+
+                match summary {
+                    Some(FileSummary::Image {
+                        width,
+                        height,
+                        palette_depth,
+                        palette_hash,
+                    }) => {
+                        entry.width = Some(*width);
+                        entry.height = Some(*height);
+                        entry.palette_depth = *palette_depth;
+                        entry.palette_sha256 = palette_hash.clone();
+                    }
+                    Some(FileSummary::Text {
+                        characters,
+                        encoding,
+                    }) => {
+                        entry.characters = Some(*characters);
+                        entry.encoding = encoding.clone();
+                    }
+                    Some(FileSummary::Removed) => entry.status = ManifestStatus::Removed,
+                    Some(FileSummary::UpToDate) | None => {}
+                }
+
+                if matches!(
+                    entry.status,
+                    ManifestStatus::Converted | ManifestStatus::UpToDate
+                ) {
+                    entry.output_sha256 = file_hash(&report.output_path);
+                }
+
+                entry
+            })
+            .collect();
+
+        Self {
+            converter_version: env!("CARGO_PKG_VERSION").to_owned(),
+            direction,
+            input_root: Some(input_root.to_owned()),
+            output_root: Some(output_root.to_owned()),
+            files,
+        }
+    }
+
+    /// Describes the outputs by their contents only, so that the same outputs always result in
+    /// the same manifest: up to date outputs are listed as converted, removed outputs are left
+    /// out and the roots are removed from the manifest and from the quoted paths in the texts of
+    /// errors and warnings.
+    pub(crate) fn make_reproducible(&mut self) {
+        let root_prefixes: Vec<String> = [self.input_root.take(), self.output_root.take()]
+            .iter()
+            .flatten()
+            .map(|root| format!("'{}{}", root.display(), MAIN_SEPARATOR))
+            .collect();
+        let strip_roots = |text: &mut String| {
+            for prefix in &root_prefixes {
+                *text = text.replace(prefix, "'");
+            }
+        };
+
+        self.files
+            .retain(|entry| entry.status != ManifestStatus::Removed);
+        for entry in &mut self.files {
+            if entry.status == ManifestStatus::UpToDate {
+                entry.status = ManifestStatus::Converted;
+            }
+            if let Some(error) = &mut entry.error {
+                strip_roots(error);
+            }
+            for warning in &mut entry.warnings {
+                strip_roots(warning);
+            }
+        }
+    }
+
+    pub fn to_json(&self) -> Result<String> {
+        serde_json::to_string_pretty(self).wrap_err("Unable to serialize the manifest.")
+    }
+
+    pub fn write(&self, filename: &Path) -> Result<()> {
+        let json = self.to_json()?;
+        create_output_file(filename)?
+            .write_all(json.as_bytes())
+            .wrap_err_with(|| format!("Unable to write to '{}'.", filename.display()))
+    }
+}
@@ -0,0 +1,319 @@
+use crate::{
+    fs_util::{create_output_file, sha256_hex},
+    gfx::JonathanImage,
+    pcx::{decode_pcx_with_mode, DecodeMode, PcxPixels},
+    source::{DirectorySource, InputSource},
+};
+use eyre::{bail, Result, WrapErr};
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt,
+    fmt::Write as _,
+    fs::DirBuilder,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+// -------------------------------------------------------------------------------------------------
+
+/// How the color values of a palette are interpreted.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum PaletteMode {
+    /// Detects 6 bit VGA palettes, see [`detect_palette_depth`].
+    #[default]
+    Auto,
+    /// The palette contains regular 8 bit color values.
+    EightBit,
+    /// The palette contains 6 bit VGA color values from 0 to 63.
+    SixBit,
+}
+
+/// The depth of the color values of a palette.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PaletteDepth {
+    EightBit,
+    SixBit,
+}
+
+impl fmt::Display for PaletteDepth {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PaletteDepth::EightBit => f.write_str("8 bit"),
+            PaletteDepth::SixBit => f.write_str("6 bit VGA"),
+        }
+    }
+}
+
+// -------------------------------------------------------------------------------------------------
+
+/// Detects 6 bit VGA palettes: all color values are in the range from 0 to 63 and the palette is
+/// not entirely black.
+pub fn detect_palette_depth(palette: &[[u8; 3]]) -> PaletteDepth {
+    let max_value = palette.iter().flatten().copied().max().unwrap_or(0);
+    if max_value > 0 && max_value <= 63 {
+        PaletteDepth::SixBit
+    } else {
+        PaletteDepth::EightBit
+    }
+}
+
+/// Expands 6 bit VGA color values to the full 8 bit range.
+pub fn expand_vga_palette(palette: &mut [[u8; 3]]) {
+    for value in palette.iter_mut().flatten() {
+        let v = *value & 0x3f;
+        *value = (v << 2) | (v >> 4);
+    }
+}
+
+/// Interprets `palette` according to `mode` and expands it to 8 bit color values if necessary.
+/// Returns the depth of the original color values.
+pub fn normalize_palette(palette: &mut [[u8; 3]], mode: PaletteMode) -> PaletteDepth {
+    let depth = match mode {
+        PaletteMode::Auto => detect_palette_depth(palette),
+        PaletteMode::EightBit => PaletteDepth::EightBit,
+        PaletteMode::SixBit => PaletteDepth::SixBit,
+    };
+
+    if depth == PaletteDepth::SixBit {
+        expand_vga_palette(palette);
+    }
+
+    depth
+}
+
+/// Returns the SHA-256 digest of the color values of `palette` as hex string. Identical palettes
+/// have the same hash.
+pub fn palette_hash(palette: &[[u8; 3]]) -> String {
+    sha256_hex(&palette.concat())
+}
+
+// -------------------------------------------------------------------------------------------------
+
+/// How the colors of an image are matched to the colors of a reference palette.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum RemapMode {
+    /// Every color is mapped to the closest color of the reference palette.
+    #[default]
+    Nearest,
+    /// Every color must be part of the reference palette.
+    Exact,
+}
+
+fn color_distance(a: [u8; 3], b: [u8; 3]) -> u32 {
+    a.iter()
+        .zip(b)
+        .map(|(&a, b)| (a as i32 - b as i32).pow(2) as u32)
+        .sum()
+}
+
+/// Re-indexes the pixels of `image` onto the palette of `reference`. Only the colors that are
+/// actually used are matched, ties are resolved in favor of the lowest index. The result has the
+/// palette and the palette depth of `reference`, both palettes have to hold 8 bit color values,
+/// see [`normalize_palette`].
+///
+/// The pixels of `transparent_index`, the color key of a sprite, keep their index and no other
+/// color is mapped to it, so that the sprite stays transparent in the same places when it is
+/// composited.
+pub fn remap_image(
+    image: &JonathanImage,
+    reference: &JonathanImage,
+    mode: RemapMode,
+    transparent_index: Option<u8>,
+) -> Result<JonathanImage> {
+    let mut used = [false; 256];
+    for &index in &image.indices {
+        used[index as usize] = true;
+    }
+
+    let mut table = [0u8; 256];
+    for (index, _) in used.iter().enumerate().filter(|(_, &used)| used) {
+        if Some(index as u8) == transparent_index {
+            table[index] = index as u8;
+            continue;
+        }
+
+        let color = image.palette[index];
+        let (nearest, distance) = reference
+            .palette
+            .iter()
+            .enumerate()
+            .filter(|&(i, _)| Some(i as u8) != transparent_index)
+            .map(|(i, &candidate)| (i as u8, color_distance(color, candidate)))
+            .min_by_key(|&(_, distance)| distance)
+            .unwrap();
+
+        if distance > 0 && mode == RemapMode::Exact {
+            bail!(
+                "The color {:?} of palette index {} is not part of the reference palette.",
+                color,
+                index
+            );
+        }
+        table[index] = nearest;
+    }
+
+    Ok(JonathanImage {
+        width: image.width,
+        height: image.height,
+        indices: image.indices.iter().map(|&i| table[i as usize]).collect(),
+        palette: reference.palette,
+        signature: image.signature,
+        palette_depth: reference.palette_depth,
+    })
+}
+
+// -------------------------------------------------------------------------------------------------
+
+/// A distinct palette and the images that use it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CatalogEntry {
+    /// Named after the first image that uses the palette.
+    pub name: String,
+    pub colors: Vec<[u8; 3]>,
+    pub images: Vec<PathBuf>,
+}
+
+/// The distinct palettes of a set of images.
+#[derive(Debug, Default)]
+pub struct PaletteCatalog {
+    pub entries: Vec<CatalogEntry>,
+    /// The images whose palette could not be extracted.
+    pub failures: Vec<(PathBuf, eyre::Report)>,
+}
+
+impl PaletteCatalog {
+    /// Adds the palette of `image` to the catalog, identical palettes are only stored once.
+    pub fn add(&mut self, image: &Path, colors: &[[u8; 3]]) {
+        match self.entries.iter_mut().find(|entry| entry.colors == colors) {
+            Some(entry) => entry.images.push(image.to_owned()),
+            None => self.entries.push(CatalogEntry {
+                name: image
+                    .file_stem()
+                    .map_or_else(|| "PALETTE".into(), |stem| stem.to_string_lossy().into()),
+                colors: colors.to_vec(),
+                images: vec![image.to_owned()],
+            }),
+        }
+    }
+
+    /// Writes every palette as JASC (`.pal`), GIMP (`.gpl`) and Adobe (`.act`) palette file and a
+    /// `PALETTES.TXT` report of the images that share each palette into `output_dir`.
+    pub fn write(&self, output_dir: &Path) -> Result<()> {
+        DirBuilder::new()
+            .recursive(true)
+            .create(output_dir)
+            .wrap_err_with(|| format!("Unable to create '{}'.", output_dir.display()))?;
+
+        for entry in &self.entries {
+            let write = |extension: &str, contents: &[u8]| -> Result<()> {
+                let filename = output_dir.join(&entry.name).with_extension(extension);
+                create_output_file(&filename)?
+                    .write_all(contents)
+                    .wrap_err_with(|| format!("Unable to write to '{}'.", filename.display()))
+            };
+
+            write("pal", format_jasc_palette(&entry.colors).as_bytes())?;
+            write(
+                "gpl",
+                format_gimp_palette(&entry.name, &entry.colors).as_bytes(),
+            )?;
+            write("act", &format_act_palette(&entry.colors))?;
+        }
+
+        let report_filename = output_dir.join("PALETTES.TXT");
+        create_output_file(&report_filename)?
+            .write_all(self.report().as_bytes())
+            .wrap_err_with(|| format!("Unable to write to '{}'.", report_filename.display()))
+    }
+
+    /// Lists the images that share each palette.
+    pub fn report(&self) -> String {
+        let mut report = String::new();
+        for entry in &self.entries {
+            let _ = writeln!(
+                report,
+                "{} ({} colors, {} images):",
+                entry.name,
+                entry.colors.len(),
+                entry.images.len()
+            );
+            for image in &entry.images {
+                let name = image.file_name().map_or_else(
+                    || image.display().to_string(),
+                    |name| name.to_string_lossy().into(),
+                );
+                let _ = writeln!(report, "    {}", name);
+            }
+        }
+        report
+    }
+}
+
+/// Extracts the palettes of all PCX files in `dir` into a catalog.
+pub fn extract_palettes(dir: &Path, palette_mode: PaletteMode) -> Result<PaletteCatalog> {
+    extract_palettes_from_source(&DirectorySource::new(dir), dir, Path::new(""), palette_mode)
+}
+
+/// Extracts the palettes of all PCX files in the directory `dir` of `source`, which was opened
+/// from `root`.
+pub(crate) fn extract_palettes_from_source(
+    source: &dyn InputSource,
+    root: &Path,
+    dir: &Path,
+    palette_mode: PaletteMode,
+) -> Result<PaletteCatalog> {
+    let mut catalog = PaletteCatalog::default();
+
+    for source_filename in source.list_files_with_extension(dir, "PCX")? {
+        let filename = root.join(&source_filename);
+        let image = source.read_file(&source_filename).and_then(|contents| {
+            decode_pcx_with_mode(&contents, DecodeMode::Strict)
+                .wrap_err_with(|| format!("Unable to decode '{}' as PCX file.", filename.display()))
+        });
+
+        match image.map(|decoded| decoded.image.pixels) {
+            Ok(PcxPixels::Indexed { mut palette, .. }) => {
+                normalize_palette(&mut palette, palette_mode);
+                catalog.add(&filename, &palette);
+            }
+            Ok(_) => catalog.failures.push((
+                filename.clone(),
+                eyre::eyre!("'{}' is not paletted.", filename.display()),
+            )),
+            Err(err) => catalog.failures.push((filename, err)),
+        }
+    }
+
+    Ok(catalog)
+}
+
+/// Formats `colors` as JASC-PAL palette file as used by Paint Shop Pro and many other tools.
+pub fn format_jasc_palette(colors: &[[u8; 3]]) -> String {
+    let mut s = format!("JASC-PAL\r\n0100\r\n{}\r\n", colors.len());
+    for [r, g, b] in colors {
+        let _ = write!(s, "{} {} {}\r\n", r, g, b);
+    }
+    s
+}
+
+/// Formats `colors` as GIMP palette file.
+pub fn format_gimp_palette(name: &str, colors: &[[u8; 3]]) -> String {
+    let mut s = format!("GIMP Palette\nName: {}\nColumns: 16\n#\n", name);
+    for (i, [r, g, b]) in colors.iter().enumerate() {
+        let _ = writeln!(s, "{:3} {:3} {:3}\tIndex {}", r, g, b, i);
+    }
+    s
+}
+
+/// Formats `colors` as Adobe color table: 256 RGB entries followed by the number of colors and
+/// the unused transparent index.
+pub fn format_act_palette(colors: &[[u8; 3]]) -> Vec<u8> {
+    let mut data = vec![0u8; 256 * 3];
+    for (entry, rgb) in data.chunks_exact_mut(3).zip(colors) {
+        entry.copy_from_slice(rgb);
+    }
+    data.extend_from_slice(&(colors.len().min(256) as u16).to_be_bytes());
+    data.extend_from_slice(&0xffffu16.to_be_bytes());
+    data
+}
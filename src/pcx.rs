@@ -0,0 +1,482 @@
+use crate::{gfx::JonathanImage, palette::PaletteDepth};
+use eyre::{bail, Result};
+
+// -------------------------------------------------------------------------------------------------
+
+const PCX_HEADER_SIZE: usize = 128;
+const PCX_PALETTE_SIZE: usize = 256 * 3;
+const PCX_PALETTE_MARKER: u8 = 0x0c;
+const PCX_MAX_RUN_LENGTH: usize = 0x3f;
+const PCX_SIGNATURE: [u8; 4] = [0x0a, 0x05, 0x01, 0x08];
+/// The largest image data decoded in [`DecodeMode::Lenient`], 64 MiB.
+const PCX_MAX_LENIENT_IMAGE_SIZE: usize = 64 << 20;
+
+// -------------------------------------------------------------------------------------------------
+
+/// How [`decode_pcx_with_mode`] deals with damaged files.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum DecodeMode {
+    /// Fails on truncated image data or a missing palette.
+    #[default]
+    Strict,
+    /// Decodes as many rows as possible and fills the remaining rows with `fill_index`. A missing
+    /// palette is replaced by a grayscale palette.
+    Lenient { fill_index: u8 },
+}
+
+/// The pixels of a decoded PCX file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PcxPixels {
+    /// One palette index per pixel, the palette has `2^bits_per_pixel` entries.
+    Indexed {
+        bits_per_pixel: u8,
+        indices: Vec<u8>,
+        palette: Vec<[u8; 3]>,
+    },
+    /// Three bytes per pixel.
+    Rgb(Vec<u8>),
+    /// Four bytes per pixel.
+    Rgba(Vec<u8>),
+}
+
+/// A decoded PCX file of any variant.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PcxImage {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: PcxPixels,
+    /// The first four bytes of the PCX file, obfuscated in the game's files.
+    pub signature: Option<[u8; 4]>,
+}
+
+impl PcxImage {
+    /// Converts a paletted image to a [`JonathanImage`], true color images are rejected.
+    pub fn into_jonathan_image(self) -> Result<JonathanImage> {
+        match self.pixels {
+            PcxPixels::Indexed {
+                indices,
+                palette: colors,
+                ..
+            } => {
+                let mut palette = [[0u8; 3]; 256];
+                palette[..colors.len()].copy_from_slice(&colors);
+
+                Ok(JonathanImage {
+                    width: self.width,
+                    height: self.height,
+                    indices,
+                    palette,
+                    signature: self.signature,
+                    palette_depth: PaletteDepth::EightBit,
+                })
+            }
+            _ => bail!("The PCX file is not paletted."),
+        }
+    }
+}
+
+/// A decoded PCX file together with the problems that were worked around while decoding it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DecodedPcx {
+    pub image: PcxImage,
+    pub warnings: Vec<String>,
+}
+
+struct PcxHeader {
+    encoding: u8,
+    bits_per_pixel: u8,
+    width: usize,
+    height: usize,
+    planes: usize,
+    bytes_per_line: usize,
+    ega_palette: [[u8; 3]; 16],
+}
+
+// -------------------------------------------------------------------------------------------------
+
+/// Formats a PCX signature as hex bytes separated by spaces, e.g. `0A 05 01 08`.
+pub fn format_signature(signature: [u8; 4]) -> String {
+    signature
+        .iter()
+        .map(|b| format!("{:02X}", b))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+pub(crate) fn parse_signature(text: &str) -> Option<[u8; 4]> {
+    let mut signature = [0u8; 4];
+    let mut bytes = text.split_whitespace();
+    for b in signature.iter_mut() {
+        *b = u8::from_str_radix(bytes.next()?, 16).ok()?;
+    }
+
+    if bytes.next().is_none() {
+        Some(signature)
+    } else {
+        None
+    }
+}
+
+fn read_u16(data: &[u8], offset: usize) -> usize {
+    u16::from_le_bytes([data[offset], data[offset + 1]]) as usize
+}
+
+fn parse_header(data: &[u8]) -> Result<PcxHeader> {
+    if data.len() < PCX_HEADER_SIZE {
+        bail!("The data is too small to be a valid PCX file.");
+    }
+
+    let (x_min, y_min, x_max, y_max) = (
+        read_u16(data, 4),
+        read_u16(data, 6),
+        read_u16(data, 8),
+        read_u16(data, 10),
+    );
+    if x_max < x_min || y_max < y_min {
+        bail!("The PCX header contains invalid image dimensions.");
+    }
+
+    // The first four bytes of the game's PCX files are obfuscated, all of them are run length
+    // encoded 256 color images. Only regular PCX files are trusted to describe themselves.
+    let is_regular_pcx =
+        data[0] == PCX_SIGNATURE[0] && data[2] <= 1 && matches!(data[3], 1 | 2 | 4 | 8);
+    let (encoding, bits_per_pixel) = if is_regular_pcx {
+        (data[2], data[3])
+    } else {
+        (PCX_SIGNATURE[2], PCX_SIGNATURE[3])
+    };
+
+    let mut ega_palette = [[0u8; 3]; 16];
+    for (entry, rgb) in ega_palette.iter_mut().zip(data[16..64].chunks_exact(3)) {
+        entry.copy_from_slice(rgb);
+    }
+
+    Ok(PcxHeader {
+        encoding,
+        bits_per_pixel,
+        width: x_max - x_min + 1,
+        height: y_max - y_min + 1,
+        planes: data[65] as usize,
+        bytes_per_line: read_u16(data, 66),
+        ega_palette,
+    })
+}
+
+/// Decompresses the PCX run length encoding into `output` and returns the number of bytes written.
+fn decode_rle(data: &[u8], output: &mut [u8]) -> usize {
+    let mut input = data.iter();
+    let mut written = 0;
+
+    while written < output.len() {
+        let (count, value) = match input.next() {
+            Some(&b) if b >= 0xc0 => match input.next() {
+                Some(&value) => ((b & 0x3f) as usize, value),
+                None => break,
+            },
+            Some(&b) => (1, b),
+            None => break,
+        };
+
+        let count = count.min(output.len() - written);
+        output[written..written + count].fill(value);
+        written += count;
+    }
+
+    written
+}
+
+/// Decodes the contents of a 256 color PCX file of the game in [`DecodeMode::Strict`]. Paletted
+/// images with fewer colors are accepted as well.
+///
+/// The first four bytes of the game's PCX files are obfuscated, they are kept in
+/// [`JonathanImage::signature`].
+pub fn decode_pcx(data: &[u8]) -> Result<JonathanImage> {
+    decode_pcx_with_mode(data, DecodeMode::Strict)?
+        .image
+        .into_jonathan_image()
+}
+
+pub(crate) fn read_vga_palette(data: &[u8]) -> Option<[[u8; 3]; 256]> {
+    let palette_offset = data.len().checked_sub(PCX_PALETTE_SIZE + 1)?;
+    if palette_offset < PCX_HEADER_SIZE || data[palette_offset] != PCX_PALETTE_MARKER {
+        return None;
+    }
+
+    let mut palette = [[0u8; 3]; 256];
+    for (entry, rgb) in palette
+        .iter_mut()
+        .zip(data[palette_offset + 1..].chunks_exact(3))
+    {
+        entry.copy_from_slice(rgb);
+    }
+    Some(palette)
+}
+
+fn read_sample(plane: &[u8], x: usize, bits_per_pixel: usize) -> u8 {
+    let bit_offset = x * bits_per_pixel;
+    let shift = 8 - bits_per_pixel - bit_offset % 8;
+    (plane[bit_offset / 8] >> shift) & ((1u16 << bits_per_pixel) - 1) as u8
+}
+
+/// Decodes the contents of a PCX file of any variant: monochrome, 4, 16 and 256 color paletted
+/// images as well as 24 and 32 bit true color images. See [`decode_pcx`] for the game's files.
+pub fn decode_pcx_with_mode(data: &[u8], mode: DecodeMode) -> Result<DecodedPcx> {
+    let header = parse_header(data)?;
+    let mut warnings = Vec::new();
+
+    let bits_per_pixel = header.bits_per_pixel as usize;
+    let is_true_color = bits_per_pixel == 8 && (header.planes == 3 || header.planes == 4);
+    if header.planes == 0 || (!is_true_color && bits_per_pixel * header.planes > 8) {
+        bail!(
+            "PCX files with {} planes of {} bits per pixel are not supported.",
+            header.planes,
+            bits_per_pixel
+        );
+    }
+    if header.bytes_per_line * 8 < header.width * bits_per_pixel {
+        bail!("The PCX header contains an invalid number of bytes per line.");
+    }
+
+    let color_count = 1usize << (bits_per_pixel * header.planes);
+    let vga_palette = if !is_true_color && color_count == 256 {
+        read_vga_palette(data)
+    } else {
+        None
+    };
+
+    let palette = if is_true_color {
+        [[0u8; 3]; 256]
+    } else if let Some(vga_palette) = vga_palette {
+        vga_palette
+    } else if color_count == 2 && header.ega_palette[..2] == [[0u8; 3]; 2] {
+        let mut palette = [[0u8; 3]; 256];
+        palette[1] = [0xff; 3];
+        palette
+    } else if color_count <= 16 {
+        let mut palette = [[0u8; 3]; 256];
+        palette[..16].copy_from_slice(&header.ega_palette);
+        palette
+    } else if let DecodeMode::Lenient { .. } = mode {
+        warnings.push("The 256 color palette is missing, a grayscale palette is used.".to_owned());
+
+        let mut palette = [[0u8; 3]; 256];
+        for (i, entry) in palette.iter_mut().enumerate() {
+            *entry = [i as u8; 3];
+        }
+        palette
+    } else {
+        bail!("The data does not contain a 256 color PCX palette.");
+    };
+
+    let image_data = if vga_palette.is_some() {
+        &data[PCX_HEADER_SIZE..data.len() - PCX_PALETTE_SIZE - 1]
+    } else {
+        &data[PCX_HEADER_SIZE..]
+    };
+
+    // Damaged headers can describe images far larger than the data could ever decode to, they are
+    // rejected before the scanlines are allocated. Lenient decoding fills the missing rows, so only
+    // images beyond any plausible size are rejected.
+    let max_expansion = match header.encoding {
+        0 => 1,
+        1 => PCX_MAX_RUN_LENGTH,
+        encoding => bail!("The PCX encoding {} is not supported.", encoding),
+    };
+    let scanline_size = header.bytes_per_line * header.planes;
+    let image_size = scanline_size * header.height;
+    let max_image_size = match mode {
+        DecodeMode::Strict => image_data.len().saturating_mul(max_expansion),
+        DecodeMode::Lenient { .. } => PCX_MAX_LENIENT_IMAGE_SIZE,
+    };
+    if image_size > max_image_size {
+        bail!(
+            "The PCX header describes {} bytes of image data, which cannot be decoded from {} \
+             bytes.",
+            image_size,
+            image_data.len()
+        );
+    }
+
+    let mut scanlines = vec![0u8; image_size];
+    let written = match header.encoding {
+        0 => {
+            let written = image_data.len().min(scanlines.len());
+            scanlines[..written].copy_from_slice(&image_data[..written]);
+            written
+        }
+        _ => decode_rle(image_data, &mut scanlines),
+    };
+
+    let decoded_rows = written / scanline_size;
+    if decoded_rows < header.height {
+        match mode {
+            DecodeMode::Strict => bail!(
+                "The image data is truncated, only {} of {} rows could be decoded.",
+                decoded_rows,
+                header.height
+            ),
+            DecodeMode::Lenient { fill_index } => {
+                if is_true_color {
+                    scanlines[decoded_rows * scanline_size..].fill(0);
+                } else {
+                    for scanline in
+                        scanlines[decoded_rows * scanline_size..].chunks_exact_mut(scanline_size)
+                    {
+                        for (plane_index, plane) in
+                            scanline.chunks_exact_mut(header.bytes_per_line).enumerate()
+                        {
+                            let bits = (fill_index >> (plane_index * bits_per_pixel))
+                                & ((1u16 << bits_per_pixel) - 1) as u8;
+                            let mut pattern = 0u16;
+                            for _ in 0..8 / bits_per_pixel {
+                                pattern = (pattern << bits_per_pixel) | bits as u16;
+                            }
+                            plane.fill(pattern as u8);
+                        }
+                    }
+                }
+
+                warnings.push(format!(
+                    "The image data is truncated, only {} of {} rows could be decoded. The \
+                     remaining rows are filled with {}.",
+                    decoded_rows,
+                    header.height,
+                    if is_true_color {
+                        "black".to_owned()
+                    } else {
+                        format!("index {}", fill_index)
+                    }
+                ));
+            }
+        }
+    }
+
+    let pixels = if is_true_color {
+        let mut pixels = Vec::with_capacity(header.width * header.height * header.planes);
+        for scanline in scanlines.chunks_exact(scanline_size) {
+            for x in 0..header.width {
+                for plane in scanline.chunks_exact(header.bytes_per_line) {
+                    pixels.push(plane[x]);
+                }
+            }
+        }
+
+        if header.planes == 3 {
+            PcxPixels::Rgb(pixels)
+        } else {
+            PcxPixels::Rgba(pixels)
+        }
+    } else {
+        let mut indices = Vec::with_capacity(header.width * header.height);
+        for scanline in scanlines.chunks_exact(scanline_size) {
+            for x in 0..header.width {
+                let mut index = 0;
+                for (plane_index, plane) in scanline.chunks_exact(header.bytes_per_line).enumerate()
+                {
+                    index |=
+                        read_sample(plane, x, bits_per_pixel) << (plane_index * bits_per_pixel);
+                }
+                indices.push(index);
+            }
+        }
+
+        PcxPixels::Indexed {
+            bits_per_pixel: (bits_per_pixel * header.planes) as u8,
+            indices,
+            palette: palette[..color_count].to_vec(),
+        }
+    };
+
+    Ok(DecodedPcx {
+        image: PcxImage {
+            width: header.width as u32,
+            height: header.height as u32,
+            pixels,
+            signature: Some([data[0], data[1], data[2], data[3]]),
+        },
+        warnings,
+    })
+}
+
+fn encode_pcx_row(row: &[u8], pcx_data: &mut Vec<u8>) {
+    let mut remaining = row;
+    while let Some(&value) = remaining.first() {
+        let run_length = remaining
+            .iter()
+            .take(PCX_MAX_RUN_LENGTH)
+            .take_while(|&&v| v == value)
+            .count();
+
+        if run_length > 1 || value >= 0xc0 {
+            pcx_data.push(0xc0 | run_length as u8);
+        }
+        pcx_data.push(value);
+
+        remaining = &remaining[run_length..];
+    }
+}
+
+/// Encodes `image` as 256 color PCX file. The obfuscated signature of the image is written to the
+/// first four bytes as expected by the game, a regular PCX file is written if it is unknown. The
+/// palette is reduced to 6 bit color values if it was expanded from them.
+pub fn encode_pcx(image: &JonathanImage) -> Result<Vec<u8>> {
+    let width = image.width as usize;
+    let bytes_per_line = width + width % 2;
+
+    if width == 0
+        || image.height == 0
+        || bytes_per_line > u16::MAX as usize
+        || image.height > 0x10000
+    {
+        bail!(
+            "An image of {}x{} pixels cannot be stored in a PCX file.",
+            image.width,
+            image.height
+        );
+    }
+
+    if image.indices.len() != width * image.height as usize {
+        bail!(
+            "The image data does not match the image size of {}x{} pixels.",
+            image.width,
+            image.height
+        );
+    }
+
+    let mut palette = image.palette;
+    if image.palette_depth == PaletteDepth::SixBit {
+        for value in palette.iter_mut().flatten() {
+            *value >>= 2;
+        }
+    }
+
+    let mut pcx_data =
+        Vec::with_capacity(PCX_HEADER_SIZE + image.indices.len() + PCX_PALETTE_SIZE + 1);
+
+    {
+        let mut header = [0u8; PCX_HEADER_SIZE];
+        header[0..4].copy_from_slice(&image.signature.unwrap_or(PCX_SIGNATURE));
+        header[8..10].copy_from_slice(&((image.width - 1) as u16).to_le_bytes());
+        header[10..12].copy_from_slice(&((image.height - 1) as u16).to_le_bytes());
+        header[12..14].copy_from_slice(&72u16.to_le_bytes());
+        header[14..16].copy_from_slice(&72u16.to_le_bytes());
+        for (i, rgb) in palette.iter().take(16).enumerate() {
+            header[16 + i * 3..16 + i * 3 + 3].copy_from_slice(rgb);
+        }
+        header[65] = 1;
+        header[66..68].copy_from_slice(&(bytes_per_line as u16).to_le_bytes());
+        header[68..70].copy_from_slice(&1u16.to_le_bytes());
+        pcx_data.extend_from_slice(&header);
+    }
+
+    let mut row = vec![0u8; bytes_per_line];
+    for source_row in image.indices.chunks_exact(width) {
+        row[..width].copy_from_slice(source_row);
+        encode_pcx_row(&row, &mut pcx_data);
+    }
+
+    pcx_data.push(PCX_PALETTE_MARKER);
+    pcx_data.extend(palette.iter().flatten());
+
+    Ok(pcx_data)
+}
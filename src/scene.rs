@@ -0,0 +1,94 @@
+use crate::gfx::{detect_color_key, encode_rgb_png, JonathanImage, TransparentColor};
+use eyre::Result;
+
+// -------------------------------------------------------------------------------------------------
+
+/// An image drawn on top of a scene background, e.g. a `G<n>_<k>` sprite of room `ORT<n>`.
+#[derive(Clone, Debug)]
+pub struct Overlay {
+    pub image: JonathanImage,
+    /// The position of the top left corner of the overlay in the background, may be negative.
+    pub x: i32,
+    pub y: i32,
+}
+
+/// A background with all overlays drawn on top of it, as RGB pixels.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Scene {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>,
+}
+
+impl Scene {
+    pub fn encode_png(&self) -> Result<Vec<u8>> {
+        encode_rgb_png(self.width, self.height, &self.pixels)
+    }
+}
+
+// -------------------------------------------------------------------------------------------------
+
+/// Draws the overlays in the given order on top of the background. Every image keeps its own
+/// palette, the pixels of the color key of an overlay are skipped and the parts of an overlay
+/// outside the background are clipped.
+pub fn composite_scene(
+    background: &JonathanImage,
+    overlays: &[Overlay],
+    color_key: TransparentColor,
+    warnings: &mut Vec<String>,
+) -> Scene {
+    let width = background.width as usize;
+    let height = background.height as usize;
+
+    let mut pixels: Vec<u8> = background
+        .indices
+        .iter()
+        .flat_map(|&index| background.palette[index as usize])
+        .collect();
+
+    for (number, overlay) in overlays.iter().enumerate() {
+        let image = &overlay.image;
+        let transparent_index = match color_key {
+            TransparentColor::None => None,
+            TransparentColor::Index(index) => Some(index),
+            TransparentColor::AutoDetect => {
+                let color_key = detect_color_key(image);
+                if color_key.is_none() {
+                    warnings.push(format!(
+                        "No color key could be detected for overlay {}, the corners of the \
+                         image differ.",
+                        number + 1
+                    ));
+                }
+                color_key
+            }
+        };
+
+        for (row, indices) in image
+            .indices
+            .chunks_exact(image.width.max(1) as usize)
+            .enumerate()
+        {
+            let y = overlay.y as i64 + row as i64;
+            if y < 0 || y >= height as i64 {
+                continue;
+            }
+
+            for (column, &index) in indices.iter().enumerate() {
+                let x = overlay.x as i64 + column as i64;
+                if x < 0 || x >= width as i64 || Some(index) == transparent_index {
+                    continue;
+                }
+
+                let offset = (y as usize * width + x as usize) * 3;
+                pixels[offset..offset + 3].copy_from_slice(&image.palette[index as usize]);
+            }
+        }
+    }
+
+    Scene {
+        width: background.width,
+        height: background.height,
+        pixels,
+    }
+}
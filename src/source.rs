@@ -0,0 +1,365 @@
+use crate::{
+    fat::{FatEntry, FatImage},
+    fs_util::{self, has_extension, is_file_with_extension, read_file_contents},
+};
+use eyre::{bail, eyre, Result, WrapErr};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs::{read_dir, File},
+    io::Read,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+// -------------------------------------------------------------------------------------------------
+
+/// An entry of a directory of an [`InputSource`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct SourceEntry {
+    pub name: String,
+    pub is_dir: bool,
+}
+
+/// Where the game's files are read from. All paths are relative to the root of the source.
+pub(crate) trait InputSource: Sync {
+    /// Lists the entries of the directory `dir`, the empty path is the root.
+    fn read_dir(&self, dir: &Path) -> Result<Vec<SourceEntry>>;
+
+    fn read_file(&self, path: &Path) -> Result<Vec<u8>>;
+
+    fn is_dir(&self, path: &Path) -> bool {
+        self.read_dir(path).is_ok()
+    }
+
+    /// Returns the path of the directory `name` in `parent`, an existing directory whose name
+    /// only differs in case is preferred over a missing one.
+    fn find_dir_ignoring_case(&self, parent: &Path, name: &str) -> PathBuf {
+        let path = parent.join(name);
+        if self.is_dir(&path) {
+            return path;
+        }
+
+        self.read_dir(parent)
+            .unwrap_or_default()
+            .into_iter()
+            .find(|entry| entry.is_dir && entry.name.eq_ignore_ascii_case(name))
+            .map_or(path, |entry| parent.join(entry.name))
+    }
+
+    /// Returns the sorted paths of all files in `dir` with the given extension.
+    fn list_files_with_extension(&self, dir: &Path, extension_upper: &str) -> Result<Vec<PathBuf>> {
+        let mut filenames: Vec<PathBuf> = self
+            .read_dir(dir)?
+            .into_iter()
+            .filter(|entry| !entry.is_dir && has_extension(Path::new(&entry.name), extension_upper))
+            .map(|entry| dir.join(entry.name))
+            .collect();
+
+        filenames.sort();
+        Ok(filenames)
+    }
+}
+
+// -------------------------------------------------------------------------------------------------
+
+/// A directory of the real file system.
+pub(crate) struct DirectorySource {
+    root: PathBuf,
+}
+
+impl DirectorySource {
+    pub(crate) fn new(root: &Path) -> Self {
+        Self {
+            root: root.to_owned(),
+        }
+    }
+
+    /// Converts a path below the root to a path relative to the root.
+    fn relative(&self, path: PathBuf) -> PathBuf {
+        match path.strip_prefix(&self.root) {
+            Ok(relative) => relative.to_owned(),
+            Err(_) => path,
+        }
+    }
+}
+
+impl InputSource for DirectorySource {
+    fn read_dir(&self, dir: &Path) -> Result<Vec<SourceEntry>> {
+        let path = self.root.join(dir);
+        let dir_reader = read_dir(&path).wrap_err_with(|| {
+            format!(
+                "Unable to read directory '{}'. Is the provided path correct?",
+                path.display()
+            )
+        })?;
+
+        let mut entries = Vec::new();
+        for entry in dir_reader {
+            let entry = entry.wrap_err_with(|| {
+                format!("Unable to read directory entry in '{}'.", path.display())
+            })?;
+            entries.push(SourceEntry {
+                name: entry.file_name().to_string_lossy().into_owned(),
+                is_dir: entry.path().is_dir(),
+            });
+        }
+
+        Ok(entries)
+    }
+
+    fn read_file(&self, path: &Path) -> Result<Vec<u8>> {
+        read_file_contents(&self.root.join(path))
+    }
+
+    fn is_dir(&self, path: &Path) -> bool {
+        self.root.join(path).is_dir()
+    }
+
+    fn find_dir_ignoring_case(&self, parent: &Path, name: &str) -> PathBuf {
+        self.relative(fs_util::find_dir_ignoring_case(
+            &self.root.join(parent),
+            name,
+        ))
+    }
+
+    fn list_files_with_extension(&self, dir: &Path, extension_upper: &str) -> Result<Vec<PathBuf>> {
+        Ok(
+            fs_util::list_files_with_extension(&self.root.join(dir), extension_upper)?
+                .into_iter()
+                .map(|path| self.relative(path))
+                .collect(),
+        )
+    }
+}
+
+// -------------------------------------------------------------------------------------------------
+
+/// The directory tree of an archive, built when the archive is opened.
+struct ArchiveIndex<T> {
+    dirs: BTreeMap<PathBuf, Vec<SourceEntry>>,
+    files: HashMap<PathBuf, T>,
+}
+
+impl<T> ArchiveIndex<T> {
+    fn new() -> Self {
+        let mut dirs = BTreeMap::new();
+        dirs.insert(PathBuf::new(), Vec::new());
+        Self {
+            dirs,
+            files: HashMap::new(),
+        }
+    }
+
+    /// Adds the directory `path` and all of its parents.
+    fn insert_dir(&mut self, path: &Path) {
+        if self.dirs.contains_key(path) {
+            return;
+        }
+
+        if let (Some(parent), Some(name)) = (path.parent(), path.file_name()) {
+            self.insert_dir(parent);
+            self.dirs.get_mut(parent).unwrap().push(SourceEntry {
+                name: name.to_string_lossy().into_owned(),
+                is_dir: true,
+            });
+        }
+        self.dirs.insert(path.to_owned(), Vec::new());
+    }
+
+    fn insert_file(&mut self, path: &Path, file: T) {
+        let (Some(parent), Some(name)) = (path.parent(), path.file_name()) else {
+            return;
+        };
+
+        self.insert_dir(parent);
+        self.dirs.get_mut(parent).unwrap().push(SourceEntry {
+            name: name.to_string_lossy().into_owned(),
+            is_dir: false,
+        });
+        self.files.insert(path.to_owned(), file);
+    }
+
+    fn read_dir(&self, dir: &Path) -> Result<Vec<SourceEntry>> {
+        self.dirs
+            .get(dir)
+            .cloned()
+            .ok_or_else(|| eyre!("The directory '{}' does not exist.", dir.display()))
+    }
+
+    fn file(&self, path: &Path) -> Result<&T> {
+        self.files
+            .get(path)
+            .ok_or_else(|| eyre!("The file '{}' does not exist.", path.display()))
+    }
+}
+
+/// Converts the `/` or `\` separated name of an archive entry to a relative path.
+fn archive_path(name: &str) -> PathBuf {
+    name.split(['/', '\\'])
+        .filter(|component| !component.is_empty() && *component != ".")
+        .collect()
+}
+
+// -------------------------------------------------------------------------------------------------
+
+/// A ZIP archive.
+pub(crate) struct ZipSource {
+    archive: Mutex<zip::ZipArchive<File>>,
+    index: ArchiveIndex<usize>,
+}
+
+impl ZipSource {
+    fn open(filename: &Path) -> Result<Self> {
+        let file = File::open(filename)
+            .wrap_err_with(|| format!("Unable to open '{}'.", filename.display()))?;
+        let mut archive = zip::ZipArchive::new(file)
+            .wrap_err_with(|| format!("Unable to read '{}' as ZIP archive.", filename.display()))?;
+
+        let mut index = ArchiveIndex::new();
+        for i in 0..archive.len() {
+            let entry = archive.by_index_raw(i)?;
+            let path = archive_path(entry.name());
+            if entry.is_dir() {
+                index.insert_dir(&path);
+            } else {
+                index.insert_file(&path, i);
+            }
+        }
+
+        Ok(Self {
+            archive: Mutex::new(archive),
+            index,
+        })
+    }
+}
+
+impl InputSource for ZipSource {
+    fn read_dir(&self, dir: &Path) -> Result<Vec<SourceEntry>> {
+        self.index.read_dir(dir)
+    }
+
+    fn read_file(&self, path: &Path) -> Result<Vec<u8>> {
+        let i = *self.index.file(path)?;
+        let mut archive = self.archive.lock().unwrap();
+        let mut entry = archive.by_index(i)?;
+
+        let mut contents = Vec::new();
+        entry
+            .read_to_end(&mut contents)
+            .wrap_err_with(|| format!("Unable to extract '{}'.", path.display()))?;
+        Ok(contents)
+    }
+}
+
+// -------------------------------------------------------------------------------------------------
+
+/// A FAT12 or FAT16 disk image.
+pub(crate) struct DiskImageSource {
+    image: FatImage,
+    index: ArchiveIndex<FatEntry>,
+}
+
+impl DiskImageSource {
+    fn open(filename: &Path) -> Result<Self> {
+        let image = FatImage::new(read_file_contents(filename)?)
+            .wrap_err_with(|| format!("Unable to read '{}' as disk image.", filename.display()))?;
+
+        let mut index = ArchiveIndex::new();
+        let mut pending = vec![(PathBuf::new(), image.root_entries()?)];
+        while let Some((dir, entries)) = pending.pop() {
+            for entry in entries {
+                let path = dir.join(&entry.name);
+                if entry.is_dir {
+                    index.insert_dir(&path);
+                    // Directories nested deeper than DOS allows point to a corrupt image.
+                    if path.components().count() < 32 {
+                        pending.push((path, image.dir_entries(&entry)?));
+                    }
+                } else {
+                    index.insert_file(&path, entry);
+                }
+            }
+        }
+
+        Ok(Self { image, index })
+    }
+}
+
+impl InputSource for DiskImageSource {
+    fn read_dir(&self, dir: &Path) -> Result<Vec<SourceEntry>> {
+        self.index.read_dir(dir)
+    }
+
+    fn read_file(&self, path: &Path) -> Result<Vec<u8>> {
+        self.image
+            .file_contents(self.index.file(path)?)
+            .wrap_err_with(|| format!("Unable to read '{}' from the disk image.", path.display()))
+    }
+}
+
+// -------------------------------------------------------------------------------------------------
+
+/// Opens `root` as source: a directory, a ZIP archive (`.zip`) or a FAT disk image (`.img`,
+/// `.ima`, `.dsk`, `.vfd`).
+pub(crate) fn open_source(root: &Path) -> Result<Box<dyn InputSource>> {
+    if !root.is_file() {
+        return Ok(Box::new(DirectorySource::new(root)));
+    }
+
+    if is_file_with_extension(root, "ZIP") {
+        Ok(Box::new(ZipSource::open(root)?))
+    } else if ["IMG", "IMA", "DSK", "VFD"]
+        .iter()
+        .any(|extension| is_file_with_extension(root, extension))
+    {
+        Ok(Box::new(DiskImageSource::open(root)?))
+    } else {
+        bail!(
+            "'{}' is neither a directory nor a ZIP archive or disk image.",
+            root.display()
+        )
+    }
+}
+
+/// Returns the shallowest directory of `source` that contains one of the directories in `names`,
+/// archives often contain the game in a subdirectory.
+pub(crate) fn find_game_dir(source: &dyn InputSource, names: &[&str]) -> Option<PathBuf> {
+    let mut pending = vec![PathBuf::new()];
+
+    while !pending.is_empty() {
+        let mut next = Vec::new();
+        for dir in pending {
+            let entries = source.read_dir(&dir).unwrap_or_default();
+            if entries.iter().any(|entry| {
+                entry.is_dir && names.iter().any(|n| entry.name.eq_ignore_ascii_case(n))
+            }) {
+                return Some(dir);
+            }
+            next.extend(
+                entries
+                    .into_iter()
+                    .filter(|entry| entry.is_dir)
+                    .map(|entry| dir.join(entry.name)),
+            );
+        }
+        pending = next;
+        pending.sort();
+    }
+
+    None
+}
+
+/// Opens `root` with [`open_source`] and returns it together with the directory of the game in
+/// it. Archives and disk images are searched for a directory named like one of `names`.
+pub(crate) fn open_game_source(
+    root: &Path,
+    names: &[&str],
+) -> Result<(Box<dyn InputSource>, PathBuf)> {
+    let source = open_source(root)?;
+    let game_dir = if root.is_file() {
+        find_game_dir(source.as_ref(), names).unwrap_or_default()
+    } else {
+        PathBuf::new()
+    };
+    Ok((source, game_dir))
+}
@@ -0,0 +1,76 @@
+use crate::{
+    converter::{Direction, FileSummary, ResourceKind},
+    fs_util::{create_output_file, read_file_contents, sha256_hex},
+};
+use eyre::{Result, WrapErr};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, io::Write, path::Path};
+
+// -------------------------------------------------------------------------------------------------
+
+/// The state of the last incremental conversion, kept in the output root.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct ConversionState {
+    /// Identifies the converter version and the options the outputs of every resource kind were
+    /// written with.
+    pub fingerprints: BTreeMap<ResourceKind, String>,
+    pub entries: Vec<StateEntry>,
+}
+
+/// An output file written by an earlier conversion. The names are relative to the input and output
+/// directories of the resource kind.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct StateEntry {
+    pub kind: ResourceKind,
+    pub source: String,
+    pub output: String,
+    pub input_sha256: String,
+    /// The summary and the warnings of the conversion, reported again while the output is up to
+    /// date.
+    #[serde(default)]
+    pub summary: Option<FileSummary>,
+    #[serde(default)]
+    pub warnings: Vec<String>,
+}
+
+pub(crate) fn state_filename(direction: Direction) -> &'static str {
+    match direction {
+        Direction::Export => ".jonathan_converter_export.json",
+        Direction::Import => ".jonathan_converter_import.json",
+    }
+}
+
+/// Combines the converter version with the debug representation of the options that affect the
+/// output, outputs written with different options are never up to date.
+pub(crate) fn options_fingerprint(options: &impl std::fmt::Debug) -> String {
+    sha256_hex(format!("{} {:?}", env!("CARGO_PKG_VERSION"), options).as_bytes())
+}
+
+impl ConversionState {
+    /// Loads the state from `filename`, a missing or unreadable state file results in an empty
+    /// state so that everything is converted.
+    pub fn load(filename: &Path) -> Self {
+        read_file_contents(filename)
+            .ok()
+            .and_then(|contents| serde_json::from_slice(&contents).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self, filename: &Path) -> Result<()> {
+        let json = serde_json::to_string_pretty(self)
+            .wrap_err("Unable to serialize the conversion state.")?;
+        create_output_file(filename)?
+            .write_all(json.as_bytes())
+            .wrap_err_with(|| format!("Unable to write to '{}'.", filename.display()))
+    }
+
+    pub fn entries_of_kind(&self, kind: ResourceKind) -> impl Iterator<Item = &StateEntry> {
+        self.entries.iter().filter(move |entry| entry.kind == kind)
+    }
+
+    /// Replaces the entries of `kind` with `entries`.
+    pub fn replace_kind(&mut self, kind: ResourceKind, entries: Vec<StateEntry>) {
+        self.entries.retain(|entry| entry.kind != kind);
+        self.entries.extend(entries);
+    }
+}
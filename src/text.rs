@@ -0,0 +1,397 @@
+use crate::{
+    codec::{detect_encoding, CodecTable},
+    converter::FileSummary,
+    fs_util::create_output_file,
+};
+use eyre::{bail, Result, WrapErr};
+use std::{convert::TryFrom, io::Write, path::Path, sync::Arc};
+
+// -------------------------------------------------------------------------------------------------
+
+/// How bytes without a character in the text encoding of the game are handled.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Default)]
+pub enum UnmappedBytes {
+    /// Unmapped bytes are an error.
+    #[default]
+    Reject,
+    /// Unmapped bytes are decoded as escape sequences like `\x{C8}`. A backslash that would
+    /// start such a sequence is escaped as well, so that the text encodes to the same bytes.
+    Escape,
+    /// Unmapped bytes are decoded as the characters U+E000 to U+E0FF of the private use area.
+    PrivateUse,
+}
+
+/// The line ending of the text files.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum LineEnding {
+    Lf,
+    CrLf,
+}
+
+impl LineEnding {
+    fn as_str(self) -> &'static str {
+        match self {
+            LineEnding::Lf => "\n",
+            LineEnding::CrLf => "\r\n",
+        }
+    }
+}
+
+impl Default for LineEnding {
+    /// The line ending of the platform the converter was built for.
+    fn default() -> Self {
+        if cfg!(windows) {
+            LineEnding::CrLf
+        } else {
+            LineEnding::Lf
+        }
+    }
+}
+
+/// The character encoding of the text files.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Default)]
+pub enum TextFileEncoding {
+    #[default]
+    Utf8,
+    /// UTF-16 in little endian byte order, as used by Windows.
+    Utf16,
+    /// ISO 8859-1, characters above U+00FF can not be written.
+    Latin1,
+}
+
+/// The settings of the text conversion.
+#[derive(Clone, Debug)]
+pub(crate) struct TextOptions {
+    pub unmapped_bytes: UnmappedBytes,
+    pub codec_table: Arc<CodecTable>,
+    /// Whether the codec table and the shift of its bytes are detected for every TCT file.
+    pub detect_encoding: bool,
+    pub line_ending: LineEnding,
+    /// Whether the text files start with a byte order mark, only used for UTF-8 and UTF-16.
+    pub byte_order_mark: bool,
+    pub file_encoding: TextFileEncoding,
+}
+
+impl Default for TextOptions {
+    fn default() -> Self {
+        Self {
+            unmapped_bytes: UnmappedBytes::default(),
+            codec_table: Arc::default(),
+            detect_encoding: false,
+            line_ending: LineEnding::default(),
+            byte_order_mark: true,
+            file_encoding: TextFileEncoding::default(),
+        }
+    }
+}
+
+/// A byte of a TCT file without a character in the text encoding of the game.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct UnmappedByte {
+    pub byte: u8,
+    pub offset: usize,
+    /// The position of the byte in the decoded text, starting at 1.
+    pub line: usize,
+    pub column: usize,
+}
+
+/// A decoded TCT file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DecodedText {
+    pub text: String,
+    /// The bytes that were decoded according to [`UnmappedBytes`].
+    pub unmapped: Vec<UnmappedByte>,
+}
+
+const PRIVATE_USE_BASE: u32 = 0xe000;
+
+/// Returns the escape sequence of `byte` written in [`UnmappedBytes::Escape`] mode.
+pub fn escape_byte(byte: u8) -> String {
+    format!("\\x{{{:02X}}}", byte)
+}
+
+/// Decodes the contents of a TCT file of the game. Lines are separated by `'\n'`.
+pub fn decode_tct(data: &[u8]) -> Result<String> {
+    Ok(decode_tct_with_mode(data, UnmappedBytes::Reject)?.text)
+}
+
+/// Decodes the contents of a TCT file of the game, bytes without a character are handled according
+/// to `unmapped_bytes`.
+pub fn decode_tct_with_mode(data: &[u8], unmapped_bytes: UnmappedBytes) -> Result<DecodedText> {
+    decode_tct_with_table(data, CodecTable::german(), unmapped_bytes)
+}
+
+/// Decodes the contents of a TCT file with the character mapping of `table`.
+pub fn decode_tct_with_table(
+    data: &[u8],
+    table: &CodecTable,
+    unmapped_bytes: UnmappedBytes,
+) -> Result<DecodedText> {
+    let mut s = String::with_capacity(data.len());
+    let mut unmapped = Vec::new();
+    let (mut line, mut column) = (1, 1);
+
+    let escape_start = [table.encode_char('x'), table.encode_char('{')];
+    let backslash = table.encode_char('\\');
+
+    for (offset, &c) in data.iter().enumerate() {
+        let starts_escape = || {
+            data[offset + 1..]
+                .iter()
+                .take(2)
+                .map(|&byte| Some(byte))
+                .eq(escape_start)
+        };
+
+        match (table.decode_byte(c), unmapped_bytes) {
+            (Some(_), UnmappedBytes::Escape) if Some(c) == backslash && starts_escape() => {
+                s.push_str(&escape_byte(c))
+            }
+            (Some(decoded), _) => s.push(decoded),
+            (None, UnmappedBytes::Reject) => {
+                bail!("Illegal character {} at offset {}.", c, offset)
+            }
+            (None, UnmappedBytes::Escape) => s.push_str(&escape_byte(c)),
+            (None, UnmappedBytes::PrivateUse) => {
+                s.push(char::from_u32(PRIVATE_USE_BASE + c as u32).unwrap())
+            }
+        }
+
+        if table.decode_byte(c).is_none() {
+            unmapped.push(UnmappedByte {
+                byte: c,
+                offset,
+                line,
+                column,
+            });
+        }
+
+        if table.decode_byte(c) == Some('\n') {
+            line += 1;
+            column = 1;
+        } else {
+            column += 1;
+        }
+    }
+
+    Ok(DecodedText { text: s, unmapped })
+}
+
+/// Encodes `text` in the text encoding of the game. Lines have to be separated by `'\n'`.
+pub fn encode_tct(text: &str) -> Result<Vec<u8>> {
+    encode_tct_with_mode(text, UnmappedBytes::Reject)
+}
+
+/// Parses the escape sequence `\x{HH}` at the start of `s`.
+fn parse_escape(s: &str) -> Option<u8> {
+    let hex = s.strip_prefix("\\x{")?.get(..3)?.strip_suffix('}')?;
+    u8::from_str_radix(hex, 16).ok()
+}
+
+/// Encodes `text` in the text encoding of the game. Escape sequences or private use characters
+/// are encoded as the original bytes according to `unmapped_bytes`.
+pub fn encode_tct_with_mode(text: &str, unmapped_bytes: UnmappedBytes) -> Result<Vec<u8>> {
+    encode_tct_with_table(text, CodecTable::german(), unmapped_bytes)
+}
+
+/// Encodes `text` with the character mapping of `table`.
+pub fn encode_tct_with_table(
+    text: &str,
+    table: &CodecTable,
+    unmapped_bytes: UnmappedBytes,
+) -> Result<Vec<u8>> {
+    let mut data = Vec::with_capacity(text.len());
+
+    for (line_index, line) in text.split('\n').enumerate() {
+        if line_index > 0 {
+            match table.encode_char('\n') {
+                Some(byte) => data.push(byte),
+                None => bail!("The codec table has no byte for line breaks."),
+            }
+        }
+
+        let mut chars = line.char_indices().enumerate();
+        while let Some((column_index, (i, c))) = chars.next() {
+            let escaped = match unmapped_bytes {
+                UnmappedBytes::Escape if c == '\\' => parse_escape(&line[i..]),
+                _ => None,
+            };
+            if let Some(byte) = escaped {
+                data.push(byte);
+                // Skips the rest of the escape sequence.
+                chars.nth(4);
+                continue;
+            }
+
+            let code = c as u32;
+            let byte = match table.encode_char(c) {
+                Some(byte) => byte,
+                None if unmapped_bytes == UnmappedBytes::PrivateUse
+                    && (PRIVATE_USE_BASE..PRIVATE_USE_BASE + 256).contains(&code) =>
+                {
+                    (code - PRIVATE_USE_BASE) as u8
+                }
+                None => bail!(
+                    "Character '{}' (U+{:04X}) at line {}, column {} cannot be represented in \
+                         the text encoding of the game.",
+                    c.escape_debug(),
+                    c as u32,
+                    line_index + 1,
+                    column_index + 1
+                ),
+            };
+            data.push(byte);
+        }
+    }
+
+    Ok(data)
+}
+
+const UTF8_BOM: &[u8] = &[0xef, 0xbb, 0xbf];
+const UTF16_BOM: &[u8] = &[0xff, 0xfe];
+
+/// Encodes `text`, whose lines are separated by `'\n'`, as contents of a text file.
+fn encode_text_file(text: &str, options: &TextOptions) -> Result<Vec<u8>> {
+    let text = text.replace('\n', options.line_ending.as_str());
+
+    Ok(match options.file_encoding {
+        TextFileEncoding::Utf8 => {
+            let bom = if options.byte_order_mark {
+                UTF8_BOM
+            } else {
+                &[]
+            };
+            [bom, text.as_bytes()].concat()
+        }
+        TextFileEncoding::Utf16 => {
+            let bom = if options.byte_order_mark {
+                UTF16_BOM
+            } else {
+                &[]
+            };
+            bom.iter()
+                .copied()
+                .chain(text.encode_utf16().flat_map(u16::to_le_bytes))
+                .collect()
+        }
+        TextFileEncoding::Latin1 => {
+            let mut data = Vec::with_capacity(text.len());
+            for (line_index, line) in text.split('\n').enumerate() {
+                if line_index > 0 {
+                    data.push(b'\n');
+                }
+                for (column_index, c) in line.chars().enumerate() {
+                    match u8::try_from(c as u32) {
+                        Ok(byte) => data.push(byte),
+                        Err(_) => bail!(
+                            "Character '{}' (U+{:04X}) at line {}, column {} cannot be \
+                             represented in Latin-1.",
+                            c.escape_debug(),
+                            c as u32,
+                            line_index + 1,
+                            column_index + 1
+                        ),
+                    }
+                }
+            }
+            data
+        }
+    })
+}
+
+/// Decodes the contents of a text file, a byte order mark takes precedence over the configured
+/// encoding. The lines of the result are separated by `'\n'`.
+fn decode_text_file(data: &[u8], options: &TextOptions) -> Result<String> {
+    let (data, file_encoding) = if let Some(data) = data.strip_prefix(UTF8_BOM) {
+        (data, TextFileEncoding::Utf8)
+    } else if let Some(data) = data.strip_prefix(UTF16_BOM) {
+        (data, TextFileEncoding::Utf16)
+    } else {
+        (data, options.file_encoding)
+    };
+
+    let text = match file_encoding {
+        TextFileEncoding::Utf8 => std::str::from_utf8(data)
+            .wrap_err("The file is not a UTF-8 text file.")?
+            .to_owned(),
+        TextFileEncoding::Utf16 => {
+            if data.len() % 2 != 0 {
+                bail!("The file is not a UTF-16 text file.");
+            }
+            let units: Vec<u16> = data
+                .chunks_exact(2)
+                .map(|unit| u16::from_le_bytes([unit[0], unit[1]]))
+                .collect();
+            String::from_utf16(&units).wrap_err("The file is not a UTF-16 text file.")?
+        }
+        TextFileEncoding::Latin1 => data.iter().map(|&byte| byte as char).collect(),
+    };
+
+    Ok(text.replace("\r\n", "\n"))
+}
+
+pub(crate) fn convert_txt(
+    input_filename: &Path,
+    input_contents: &[u8],
+    output_filename: &Path,
+    options: &TextOptions,
+    warnings: &mut Vec<String>,
+) -> Result<FileSummary> {
+    let detected = options.detect_encoding.then(|| {
+        let mut tables = vec![&*options.codec_table];
+        if *options.codec_table != *CodecTable::german() {
+            tables.push(CodecTable::german());
+        }
+        detect_encoding(input_contents, &tables)
+    });
+    let table = detected
+        .as_ref()
+        .map_or(&*options.codec_table, |(_, table)| table);
+
+    let DecodedText { text, unmapped } =
+        decode_tct_with_table(input_contents, table, options.unmapped_bytes)
+            .wrap_err_with(|| format!("Unable to decode '{}'.", input_filename.display()))?;
+
+    for unmapped_byte in unmapped {
+        warnings.push(format!(
+            "Unmapped byte 0x{:02X} at offset {} (line {}, column {}).",
+            unmapped_byte.byte, unmapped_byte.offset, unmapped_byte.line, unmapped_byte.column
+        ));
+    }
+
+    let converted_file_contents = encode_text_file(&text, options)
+        .wrap_err_with(|| format!("Unable to encode '{}'.", input_filename.display()))?;
+
+    let mut file = create_output_file(output_filename)?;
+    file.write_all(&converted_file_contents)
+        .wrap_err_with(|| format!("Unable to write to '{}'.", output_filename.display()))?;
+
+    Ok(FileSummary::Text {
+        characters: text.chars().count(),
+        encoding: detected.map(|(encoding, _)| encoding),
+    })
+}
+
+pub(crate) fn convert_tct(
+    input_filename: &Path,
+    input_contents: &[u8],
+    output_filename: &Path,
+    options: &TextOptions,
+    _warnings: &mut Vec<String>,
+) -> Result<FileSummary> {
+    let text = decode_text_file(input_contents, options)
+        .wrap_err_with(|| format!("Unable to read '{}'.", input_filename.display()))?;
+
+    let converted_file_contents =
+        encode_tct_with_table(&text, &options.codec_table, options.unmapped_bytes)
+            .wrap_err_with(|| format!("Unable to encode '{}'.", input_filename.display()))?;
+
+    let mut file = create_output_file(output_filename)?;
+    file.write_all(&converted_file_contents)
+        .wrap_err_with(|| format!("Unable to write to '{}'.", output_filename.display()))?;
+
+    Ok(FileSummary::Text {
+        characters: text.chars().count(),
+        encoding: None,
+    })
+}
@@ -0,0 +1,698 @@
+use jonathan_converter::{
+    decode_png, detect_color_key, encode_pcx, palette_hash, run_converter, ConversionFailed,
+    ConversionSummary, Converter, Direction, DirectoryLayout, FileSummary, JonathanImage,
+    LineEnding, PaletteDepth, PaletteMode, ResourceKind, TextFileEncoding, TransparencyOutput,
+    TransparentColor, UnmappedBytes,
+};
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+
+fn create_game_dir(path: &Path) {
+    fs::create_dir_all(path.join("TEXT")).unwrap();
+    // "Hallo" followed by a line break in the game's text encoding.
+    fs::write(
+        path.join("TEXT").join("S0.TCT"),
+        [82, 107, 118, 118, 121, 10],
+    )
+    .unwrap();
+}
+
+#[test]
+fn test_converter_writes_into_output_root() {
+    let tempdir = tempfile::tempdir().unwrap();
+    let game_dir = tempdir.path().join("game");
+    let output_dir = tempdir.path().join("output");
+    create_game_dir(&game_dir);
+
+    let reports = Converter::new(&game_dir)
+        .output_root(&output_dir)
+        .resource_kinds(&[ResourceKind::Texts])
+        .convert()
+        .unwrap();
+
+    assert_eq!(reports.len(), 1);
+    let report = &reports[0];
+    assert_eq!(report.kind, ResourceKind::Texts);
+    assert_eq!(
+        report.output_path,
+        output_dir.join("TEXT_TXT").join("S0.TXT")
+    );
+    assert_eq!(
+        *report.result.as_ref().unwrap(),
+        FileSummary::Text {
+            characters: 6,
+            encoding: None
+        }
+    );
+    assert!(report.output_path.is_file());
+    assert!(!game_dir.join("TEXT_TXT").exists());
+}
+
+#[test]
+fn test_converter_fails_on_missing_input_dir() {
+    let tempdir = tempfile::tempdir().unwrap();
+    create_game_dir(tempdir.path());
+
+    assert!(Converter::new(tempdir.path())
+        .resource_kinds(&[ResourceKind::Graphics])
+        .convert()
+        .is_err());
+}
+
+#[test]
+fn test_converter_imports_texts() {
+    let tempdir = tempfile::tempdir().unwrap();
+    let game_dir = tempdir.path();
+    fs::create_dir_all(game_dir.join("TEXT_TXT")).unwrap();
+    fs::write(
+        game_dir.join("TEXT_TXT").join("S0.TXT"),
+        "\u{feff}Hallo\r\nTschüß\r\n",
+    )
+    .unwrap();
+    fs::write(game_dir.join("TEXT_TXT").join("S1.TXT"), "Hallo\nWelt €").unwrap();
+
+    let reports = Converter::new(game_dir)
+        .direction(Direction::Import)
+        .resource_kinds(&[ResourceKind::Texts])
+        .convert()
+        .unwrap();
+
+    assert_eq!(reports.len(), 2);
+    assert_eq!(
+        fs::read(game_dir.join("TEXT").join("S0.TCT")).unwrap(),
+        [82, 107, 118, 118, 121, 10, 94, 125, 109, 114, 139, 235, 10]
+    );
+
+    let err = format!("{:#}", reports[1].result.as_ref().unwrap_err());
+    assert!(err.contains("S1.TXT"), "{}", err);
+    assert!(err.contains("line 2, column 6"), "{}", err);
+}
+
+#[test]
+fn test_converter_writes_text_file_options() {
+    let tempdir = tempfile::tempdir().unwrap();
+    let game_dir = tempdir.path();
+    fs::create_dir_all(game_dir.join("TEXT")).unwrap();
+    // "Grüße" and "A" on two lines.
+    let tct = [81, 124, 139, 235, 111, 10, 75];
+    fs::write(game_dir.join("TEXT").join("S0.TCT"), tct).unwrap();
+    let txt = game_dir.join("TEXT_TXT").join("S0.TXT");
+
+    let convert = |direction, line_ending, byte_order_mark, file_encoding| {
+        let reports = Converter::new(game_dir)
+            .direction(direction)
+            .resource_kinds(&[ResourceKind::Texts])
+            .line_ending(line_ending)
+            .byte_order_mark(byte_order_mark)
+            .text_file_encoding(file_encoding)
+            .convert()
+            .unwrap();
+        assert!(reports[0].result.is_ok(), "{:?}", reports[0].result);
+    };
+
+    convert(
+        Direction::Export,
+        LineEnding::Lf,
+        false,
+        TextFileEncoding::Utf8,
+    );
+    assert_eq!(fs::read(&txt).unwrap(), "Grüße\nA".as_bytes());
+
+    convert(
+        Direction::Export,
+        LineEnding::CrLf,
+        true,
+        TextFileEncoding::Utf16,
+    );
+    assert_eq!(
+        fs::read(&txt).unwrap(),
+        [0xff, 0xfe, b'G', 0, b'r', 0, 0xfc, 0, 0xdf, 0, b'e', 0, b'\r', 0, b'\n', 0, b'A', 0]
+    );
+    // The byte order mark takes precedence over the configured encoding.
+    fs::remove_file(game_dir.join("TEXT").join("S0.TCT")).unwrap();
+    convert(
+        Direction::Import,
+        LineEnding::Lf,
+        true,
+        TextFileEncoding::Utf8,
+    );
+    assert_eq!(fs::read(game_dir.join("TEXT").join("S0.TCT")).unwrap(), tct);
+
+    convert(
+        Direction::Export,
+        LineEnding::CrLf,
+        true,
+        TextFileEncoding::Latin1,
+    );
+    assert_eq!(
+        fs::read(&txt).unwrap(),
+        [b'G', b'r', 0xfc, 0xdf, b'e', b'\r', b'\n', b'A']
+    );
+    fs::remove_file(game_dir.join("TEXT").join("S0.TCT")).unwrap();
+    convert(
+        Direction::Import,
+        LineEnding::Lf,
+        true,
+        TextFileEncoding::Latin1,
+    );
+    assert_eq!(fs::read(game_dir.join("TEXT").join("S0.TCT")).unwrap(), tct);
+}
+
+#[test]
+fn test_converter_imports_graphics() {
+    let tempdir = tempfile::tempdir().unwrap();
+    let game_dir = tempdir.path();
+    fs::create_dir_all(game_dir.join("GRAFIK")).unwrap();
+
+    let image = JonathanImage {
+        width: 4,
+        height: 2,
+        indices: vec![1, 1, 1, 2, 3, 200, 200, 255],
+        palette: [[100, 150, 200]; 256],
+        signature: Some([0xde, 0xad, 0xbe, 0xef]),
+        palette_depth: PaletteDepth::EightBit,
+    };
+    let original = encode_pcx(&image).unwrap();
+    fs::write(game_dir.join("GRAFIK").join("ORT1.PCX"), &original).unwrap();
+
+    Converter::new(game_dir)
+        .resource_kinds(&[ResourceKind::Graphics])
+        .convert()
+        .unwrap();
+    // The header bytes are restored from the PNG file.
+    fs::remove_file(game_dir.join("GRAFIK").join("ORT1.PCX")).unwrap();
+
+    let reports = Converter::new(game_dir)
+        .direction(Direction::Import)
+        .resource_kinds(&[ResourceKind::Graphics])
+        .convert()
+        .unwrap();
+
+    assert_eq!(
+        *reports[0].result.as_ref().unwrap(),
+        FileSummary::Image {
+            width: 4,
+            height: 2,
+            palette_depth: None,
+            palette_hash: Some(palette_hash(&image.palette)),
+        }
+    );
+    assert_eq!(
+        fs::read(game_dir.join("GRAFIK").join("ORT1.PCX")).unwrap(),
+        original
+    );
+}
+
+#[test]
+fn test_converter_imports_six_bit_palettes() {
+    let tempdir = tempfile::tempdir().unwrap();
+    let game_dir = tempdir.path();
+    fs::create_dir_all(game_dir.join("GRAFIK")).unwrap();
+
+    let mut palette = [[0u8; 3]; 256];
+    for (i, entry) in palette.iter_mut().enumerate() {
+        *entry = [i as u8 % 64, 63 - i as u8 % 64, 7];
+    }
+    let image = JonathanImage {
+        width: 4,
+        height: 2,
+        indices: vec![0, 1, 2, 3, 62, 63, 64, 255],
+        palette,
+        signature: Some([0xde, 0xad, 0xbe, 0xef]),
+        palette_depth: PaletteDepth::EightBit,
+    };
+    let original = encode_pcx(&image).unwrap();
+    fs::write(game_dir.join("GRAFIK").join("ORT1.PCX"), &original).unwrap();
+
+    let reports = Converter::new(game_dir)
+        .resource_kinds(&[ResourceKind::Graphics])
+        .convert()
+        .unwrap();
+    assert!(matches!(
+        reports[0].result,
+        Ok(FileSummary::Image {
+            palette_depth: Some(PaletteDepth::SixBit),
+            ..
+        })
+    ));
+
+    Converter::new(game_dir)
+        .direction(Direction::Import)
+        .resource_kinds(&[ResourceKind::Graphics])
+        .convert()
+        .unwrap();
+
+    assert_eq!(
+        fs::read(game_dir.join("GRAFIK").join("ORT1.PCX")).unwrap(),
+        original
+    );
+
+    // An image editor removed the text chunks, the palette depth of the original PCX file is
+    // detected instead.
+    let png_filename = game_dir.join("GRAFIK_PNG").join("ORT1.PNG");
+    let exported = decode_png(&fs::read(&png_filename).unwrap()).unwrap();
+    {
+        let mut encoder = png::Encoder::new(fs::File::create(&png_filename).unwrap(), 4, 2);
+        encoder.set_color(png::ColorType::Indexed);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder.set_palette(exported.palette.concat());
+        let mut writer = encoder.write_header().unwrap();
+        writer.write_image_data(&exported.indices).unwrap();
+    }
+
+    Converter::new(game_dir)
+        .direction(Direction::Import)
+        .resource_kinds(&[ResourceKind::Graphics])
+        .convert()
+        .unwrap();
+
+    assert_eq!(
+        fs::read(game_dir.join("GRAFIK").join("ORT1.PCX")).unwrap(),
+        original
+    );
+}
+
+#[test]
+fn test_converter_writes_transparent_sprites() {
+    let tempdir = tempfile::tempdir().unwrap();
+    let game_dir = tempdir.path();
+    fs::create_dir_all(game_dir.join("GRAFIK")).unwrap();
+
+    let sprite = JonathanImage {
+        width: 3,
+        height: 3,
+        indices: vec![5, 5, 5, 5, 1, 5, 5, 2, 3],
+        palette: [[100, 150, 200]; 256],
+        signature: Some([0xde, 0xad, 0xbe, 0xef]),
+        palette_depth: PaletteDepth::EightBit,
+    };
+    assert_eq!(detect_color_key(&sprite), Some(5));
+    fs::write(
+        game_dir.join("GRAFIK").join("G1_1.PCX"),
+        encode_pcx(&sprite).unwrap(),
+    )
+    .unwrap();
+
+    Converter::new(game_dir)
+        .resource_kinds(&[ResourceKind::Graphics])
+        .transparent_color(TransparentColor::AutoDetect)
+        .transparency_output(TransparencyOutput::Rgba)
+        .convert()
+        .unwrap();
+
+    let decoder =
+        png::Decoder::new(fs::File::open(game_dir.join("GRAFIK_PNG").join("G1_1.PNG")).unwrap());
+    let mut reader = decoder.read_info().unwrap();
+    let mut rgba = vec![0; reader.output_buffer_size()];
+    reader.next_frame(&mut rgba).unwrap();
+
+    assert_eq!(reader.info().color_type, png::ColorType::Rgba);
+    assert_eq!(rgba[..8], [100, 150, 200, 0, 100, 150, 200, 0]);
+    assert_eq!(rgba[16..20], [100, 150, 200, 255]);
+}
+
+#[test]
+fn test_converter_writes_manifest() {
+    let tempdir = tempfile::tempdir().unwrap();
+    let game_dir = tempdir.path();
+    create_game_dir(game_dir);
+    fs::write(game_dir.join("TEXT").join("S1.TCT"), [81, 5]).unwrap();
+
+    Converter::new(game_dir)
+        .resource_kinds(&[ResourceKind::Texts])
+        .write_manifest(true)
+        .convert()
+        .unwrap();
+
+    let manifest: serde_json::Value =
+        serde_json::from_slice(&fs::read(game_dir.join("manifest.json")).unwrap()).unwrap();
+    let files = manifest["files"].as_array().unwrap();
+
+    assert_eq!(manifest["direction"], "export");
+    assert_eq!(files.len(), 2);
+    assert_eq!(files[0]["kind"], "texts");
+    assert_eq!(files[0]["source"], "TEXT/S0.TCT");
+    assert_eq!(files[0]["output"], "TEXT_TXT/S0.TXT");
+    assert_eq!(files[0]["status"], "converted");
+    assert_eq!(files[0]["characters"], 6);
+    assert_eq!(files[0]["output_sha256"].as_str().unwrap().len(), 64);
+    assert_eq!(files[1]["status"], "failed");
+    assert!(files[1]["error"]
+        .as_str()
+        .unwrap()
+        .contains("Illegal character"));
+    assert!(files[1].get("output_sha256").is_none());
+}
+
+#[test]
+fn test_converter_converts_incrementally() {
+    let tempdir = tempfile::tempdir().unwrap();
+    let game_dir = tempdir.path();
+    create_game_dir(game_dir);
+    fs::write(game_dir.join("TEXT").join("S1.TCT"), [82, 10]).unwrap();
+
+    let converter = Converter::new(game_dir)
+        .resource_kinds(&[ResourceKind::Texts])
+        .incremental(true);
+
+    let reports = converter.convert().unwrap();
+    assert!(reports
+        .iter()
+        .all(|report| matches!(report.result, Ok(FileSummary::Text { .. }))));
+
+    fs::write(game_dir.join("TEXT").join("S0.TCT"), [83, 10]).unwrap();
+    fs::remove_file(game_dir.join("TEXT").join("S1.TCT")).unwrap();
+    fs::write(game_dir.join("TEXT").join("S2.TCT"), [84, 10]).unwrap();
+
+    let reports = converter.convert().unwrap();
+    let results: Vec<_> = reports
+        .iter()
+        .map(|report| {
+            (
+                report.output_path.file_name().unwrap(),
+                report.result.as_ref().unwrap(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        results,
+        [
+            (
+                "S0.TXT".as_ref(),
+                &FileSummary::Text {
+                    characters: 2,
+                    encoding: None
+                }
+            ),
+            (
+                "S2.TXT".as_ref(),
+                &FileSummary::Text {
+                    characters: 2,
+                    encoding: None
+                }
+            ),
+            ("S1.TXT".as_ref(), &FileSummary::Removed),
+        ]
+    );
+    assert!(!game_dir.join("TEXT_TXT").join("S1.TXT").exists());
+
+    let reports = converter.convert().unwrap();
+    assert_eq!(reports.len(), 2);
+    assert!(reports
+        .iter()
+        .all(|report| matches!(report.result, Ok(FileSummary::UpToDate))));
+
+    // Outputs written with other options are converted again, the options of other resource
+    // kinds do not matter.
+    let reports = converter
+        .clone()
+        .palette_mode(PaletteMode::EightBit)
+        .convert()
+        .unwrap();
+    assert!(matches!(reports[0].result, Ok(FileSummary::UpToDate)));
+
+    let reports = converter
+        .clone()
+        .unmapped_bytes(UnmappedBytes::Escape)
+        .convert()
+        .unwrap();
+    assert!(matches!(reports[0].result, Ok(FileSummary::Text { .. })));
+}
+
+#[test]
+fn test_run_converter_reconverts_texts_with_changed_options() {
+    let tempdir = tempfile::tempdir().unwrap();
+    let game_dir = tempdir.path();
+    create_game_dir(game_dir);
+    fs::create_dir_all(game_dir.join("GRAFIK")).unwrap();
+
+    let converter = Converter::new(game_dir)
+        .incremental(true)
+        .byte_order_mark(false);
+    let output_filename = game_dir.join("TEXT_TXT").join("S0.TXT");
+
+    run_converter(&converter.clone().line_ending(LineEnding::Lf)).unwrap();
+    assert_eq!(fs::read(&output_filename).unwrap(), b"Hallo\n");
+
+    run_converter(&converter.clone().line_ending(LineEnding::CrLf)).unwrap();
+    assert_eq!(fs::read(&output_filename).unwrap(), b"Hallo\r\n");
+}
+
+#[test]
+fn test_converter_round_trips_all_bytes() {
+    let tempdir = tempfile::tempdir().unwrap();
+    let game_dir = tempdir.path();
+    fs::create_dir_all(game_dir.join("TEXT")).unwrap();
+    let original: Vec<u8> = (0..=255).collect();
+    fs::write(game_dir.join("TEXT").join("S1.TCT"), &original).unwrap();
+
+    for unmapped_bytes in [UnmappedBytes::Escape, UnmappedBytes::PrivateUse] {
+        let converter = Converter::new(game_dir)
+            .resource_kinds(&[ResourceKind::Texts])
+            .unmapped_bytes(unmapped_bytes)
+            .line_ending(LineEnding::CrLf);
+        let reports = converter.convert().unwrap();
+        assert!(reports[0].result.is_ok());
+
+        let reports = converter.direction(Direction::Import).convert().unwrap();
+        assert!(reports[0].result.is_ok());
+        assert_eq!(
+            fs::read(game_dir.join("TEXT").join("S1.TCT")).unwrap(),
+            original
+        );
+    }
+}
+
+#[test]
+fn test_converter_uses_directory_layout() {
+    let tempdir = tempfile::tempdir().unwrap();
+    let game_dir = tempdir.path().join("game");
+    fs::create_dir_all(game_dir.join("text")).unwrap();
+    fs::write(game_dir.join("text").join("S0.TCT"), [82, 10]).unwrap();
+
+    let reports = Converter::new(&game_dir)
+        .output_root(tempdir.path().join("output"))
+        .directory_layout(DirectoryLayout {
+            text_output: "Texte".to_owned(),
+            ..DirectoryLayout::default()
+        })
+        .resource_kinds(&[ResourceKind::Texts])
+        .convert()
+        .unwrap();
+
+    assert_eq!(reports[0].input_path, game_dir.join("text").join("S0.TCT"));
+    assert!(tempdir
+        .path()
+        .join("output")
+        .join("Texte")
+        .join("S0.TXT")
+        .is_file());
+}
+
+#[test]
+fn test_converter_converts_batch() {
+    let tempdir = tempfile::tempdir().unwrap();
+    let archive_dir = tempdir.path().join("archive");
+    let output_dir = tempdir.path().join("output");
+    create_game_dir(&archive_dir.join("retail"));
+    create_game_dir(&archive_dir.join("discs").join("demo"));
+    fs::create_dir_all(archive_dir.join("discs").join("other")).unwrap();
+
+    let converter = Converter::new(&archive_dir).output_root(&output_dir);
+    assert_eq!(
+        converter.find_installations().unwrap(),
+        [
+            archive_dir.join("discs").join("demo"),
+            archive_dir.join("retail")
+        ]
+    );
+
+    let batch_reports = converter.convert_batch().unwrap();
+    assert_eq!(batch_reports.len(), 2);
+    for batch_report in &batch_reports {
+        let reports = batch_report.result.as_ref().unwrap();
+        assert_eq!(
+            ConversionSummary::from_reports(reports),
+            ConversionSummary {
+                converted: 1,
+                ..ConversionSummary::default()
+            }
+        );
+    }
+    assert!(output_dir
+        .join("discs")
+        .join("demo")
+        .join("TEXT_TXT")
+        .join("S0.TXT")
+        .is_file());
+    assert!(output_dir
+        .join("retail")
+        .join("TEXT_TXT")
+        .join("S0.TXT")
+        .is_file());
+}
+
+#[test]
+fn test_converter_reads_zip_archives() {
+    let tempdir = tempfile::tempdir().unwrap();
+    let archive_filename = tempdir.path().join("jonathan.zip");
+
+    let mut zip = zip::ZipWriter::new(fs::File::create(&archive_filename).unwrap());
+    zip.start_file("JONATHAN/text/S0.TCT", Default::default())
+        .unwrap();
+    zip.write_all(&[82, 107, 118, 118, 121, 10]).unwrap();
+    zip.finish().unwrap();
+
+    let reports = Converter::new(&archive_filename)
+        .resource_kinds(&[ResourceKind::Texts])
+        .convert()
+        .unwrap();
+
+    assert_eq!(
+        reports[0].input_path,
+        archive_filename
+            .join("JONATHAN")
+            .join("text")
+            .join("S0.TCT")
+    );
+    assert_eq!(
+        *reports[0].result.as_ref().unwrap(),
+        FileSummary::Text {
+            characters: 6,
+            encoding: None
+        }
+    );
+    assert!(tempdir
+        .path()
+        .join("jonathan")
+        .join("TEXT_TXT")
+        .join("S0.TXT")
+        .is_file());
+}
+
+// Builds a FAT12 floppy image with the file TEXT/S0.TCT.
+fn make_disk_image() -> Vec<u8> {
+    const SECTOR: usize = 512;
+    let mut image = vec![0u8; 20 * SECTOR];
+    image[11..13].copy_from_slice(&(SECTOR as u16).to_le_bytes());
+    image[13] = 1; // sectors per cluster
+    image[14..16].copy_from_slice(&1u16.to_le_bytes()); // reserved sectors
+    image[16] = 1; // FATs
+    image[17..19].copy_from_slice(&16u16.to_le_bytes()); // root directory entries
+    image[19..21].copy_from_slice(&20u16.to_le_bytes()); // sectors
+    image[22..24].copy_from_slice(&1u16.to_le_bytes()); // sectors per FAT
+
+    // Clusters 2 and 3 are single cluster chains.
+    image[SECTOR..SECTOR + 6].copy_from_slice(&[0xf0, 0xff, 0xff, 0xff, 0xff, 0xff]);
+
+    let dir_entry = |name: &[u8; 11], attributes: u8, cluster: u16, size: u32| {
+        let mut entry = [0u8; 32];
+        entry[..11].copy_from_slice(name);
+        entry[11] = attributes;
+        entry[26..28].copy_from_slice(&cluster.to_le_bytes());
+        entry[28..32].copy_from_slice(&size.to_le_bytes());
+        entry
+    };
+
+    let root_dir = 2 * SECTOR;
+    image[root_dir..root_dir + 32].copy_from_slice(&dir_entry(b"JONATHAN   ", 0x08, 0, 0));
+    image[root_dir + 32..root_dir + 64].copy_from_slice(&dir_entry(b"TEXT       ", 0x10, 2, 0));
+
+    let data = 3 * SECTOR;
+    image[data..data + 32].copy_from_slice(&dir_entry(b"S0      TCT", 0x20, 3, 6));
+    image[data + SECTOR..data + SECTOR + 6].copy_from_slice(&[82, 107, 118, 118, 121, 10]);
+
+    image
+}
+
+#[test]
+fn test_converter_reads_disk_images() {
+    let tempdir = tempfile::tempdir().unwrap();
+    let image_filename = tempdir.path().join("DISK1.IMG");
+    fs::write(&image_filename, make_disk_image()).unwrap();
+
+    let reports = Converter::new(&image_filename)
+        .output_root(tempdir.path().join("output"))
+        .resource_kinds(&[ResourceKind::Texts])
+        .convert()
+        .unwrap();
+
+    assert_eq!(reports.len(), 1);
+    assert_eq!(
+        *reports[0].result.as_ref().unwrap(),
+        FileSummary::Text {
+            characters: 6,
+            encoding: None
+        }
+    );
+    assert_eq!(
+        fs::read_to_string(
+            tempdir
+                .path()
+                .join("output")
+                .join("TEXT_TXT")
+                .join("S0.TXT")
+        )
+        .unwrap()
+        .trim_start_matches('\u{feff}')
+        .trim_end(),
+        "Hallo"
+    );
+}
+
+#[test]
+fn test_converter_writes_reproducible_archive() {
+    let tempdir = tempfile::tempdir().unwrap();
+    let game_dirs = [tempdir.path().join("game"), tempdir.path().join("copy")];
+    for game_dir in &game_dirs {
+        create_game_dir(game_dir);
+        fs::write(game_dir.join("TEXT").join("S1.TCT"), [82, 10]).unwrap();
+        fs::write(game_dir.join("TEXT").join("S2.TCT"), [81, 5]).unwrap();
+    }
+
+    let write_archive = |game_dir: &Path, name: &str| {
+        let filename = tempdir.path().join(name);
+        Converter::new(game_dir)
+            .resource_kinds(&[ResourceKind::Texts])
+            .incremental(true)
+            .output_archive(&filename)
+            .convert()
+            .unwrap();
+        fs::read(filename).unwrap()
+    };
+
+    let archive = write_archive(&game_dirs[0], "first.zip");
+    // The outputs are up to date in the second run, the copy has different paths.
+    assert_eq!(archive, write_archive(&game_dirs[0], "second.zip"));
+    assert_eq!(archive, write_archive(&game_dirs[1], "third.zip"));
+
+    let mut zip = zip::ZipArchive::new(std::io::Cursor::new(archive)).unwrap();
+    let names: Vec<String> = (0..zip.len())
+        .map(|i| zip.by_index(i).unwrap().name().to_owned())
+        .collect();
+    assert_eq!(
+        names,
+        ["TEXT_TXT/S0.TXT", "TEXT_TXT/S1.TXT", "manifest.json"]
+    );
+}
+
+#[test]
+fn test_run_converter_fails_when_files_fail() {
+    let tempdir = tempfile::tempdir().unwrap();
+    let game_dir = tempdir.path();
+    create_game_dir(game_dir);
+
+    let converter = Converter::new(game_dir).resource_kinds(&[ResourceKind::Texts]);
+    assert!(jonathan_converter::run_converter(&converter).is_ok());
+
+    fs::write(game_dir.join("TEXT").join("S1.TCT"), [81, 5]).unwrap();
+    let err = jonathan_converter::run_converter(&converter).unwrap_err();
+    let failed = err.downcast_ref::<ConversionFailed>().unwrap();
+
+    assert_eq!(failed.failed, [game_dir.join("TEXT").join("S1.TCT")]);
+    assert_eq!(failed.total, 2);
+    assert!(err
+        .to_string()
+        .starts_with("1 of 2 files could not be converted:"));
+}
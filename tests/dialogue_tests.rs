@@ -0,0 +1,133 @@
+use jonathan_converter::{
+    encode_tct, export_dialogues, import_dialogues, run_dialogues, CodecTable, Dialogue,
+    DialogueEntry, DialogueFormat, DialogueItem, DirectoryLayout,
+};
+use std::{fs, io::Write};
+
+const DIALOGUE: &str = "Jonathan: Guten Tag!\n\
+                        Wirt: Was darf es sein?\n\
+                        1. Ein Bier, bitte.\n\
+                        2. Nichts, danke.\n\
+                        -----\n\
+                        Der Wirt schaut dich an: Er wartet.\n";
+
+#[test]
+fn test_parse_dialogue() {
+    let dialogue = Dialogue::parse(DIALOGUE);
+
+    assert_eq!(
+        dialogue.entries,
+        [
+            DialogueEntry {
+                items: vec![
+                    DialogueItem::Line {
+                        speaker: "Jonathan".to_owned(),
+                        text: "Guten Tag!".to_owned()
+                    },
+                    DialogueItem::Line {
+                        speaker: "Wirt".to_owned(),
+                        text: "Was darf es sein?".to_owned()
+                    },
+                    DialogueItem::Choice {
+                        number: 1,
+                        text: "Ein Bier, bitte.".to_owned()
+                    },
+                    DialogueItem::Choice {
+                        number: 2,
+                        text: "Nichts, danke.".to_owned()
+                    },
+                ],
+                separator: Some("-----".to_owned()),
+            },
+            DialogueEntry {
+                items: vec![DialogueItem::Narration {
+                    text: "Der Wirt schaut dich an: Er wartet.".to_owned()
+                }],
+                separator: Some("".to_owned()),
+            },
+        ]
+    );
+    assert_eq!(dialogue.to_text(), DIALOGUE);
+
+    for text in ["", "\n\n", "01. Nein", "Ohne Zeilenumbruch"] {
+        assert_eq!(Dialogue::parse(text).to_text(), text);
+    }
+}
+
+#[test]
+fn test_serialize_dialogue() {
+    let dialogue = Dialogue::parse(DIALOGUE);
+
+    for format in [DialogueFormat::Json, DialogueFormat::Yaml] {
+        let serialized = dialogue.serialize(format).unwrap();
+        assert!(serialized.contains("speaker"), "{}", serialized);
+        assert_eq!(
+            Dialogue::deserialize(&serialized, format).unwrap(),
+            dialogue
+        );
+    }
+}
+
+#[test]
+fn test_export_and_import_dialogues() {
+    let tempdir = tempfile::tempdir().unwrap();
+    let text_dir = tempdir.path().join("TEXT");
+    let dialogue_dir = tempdir.path().join("DIALOGUES");
+    fs::create_dir_all(&text_dir).unwrap();
+    fs::write(text_dir.join("D4.TCT"), encode_tct(DIALOGUE).unwrap()).unwrap();
+    fs::write(text_dir.join("S0.TCT"), encode_tct("Hallo\n").unwrap()).unwrap();
+
+    let table = CodecTable::default();
+    let report = export_dialogues(&text_dir, &dialogue_dir, DialogueFormat::Yaml, &table).unwrap();
+    assert!(report.failures.is_empty(), "{:?}", report.failures);
+    assert_eq!(report.converted.len(), 1);
+    assert!(dialogue_dir.join("D4.yaml").is_file());
+
+    let yaml = fs::read_to_string(dialogue_dir.join("D4.yaml"))
+        .unwrap()
+        .replace("Ein Bier", "Ein Wasser");
+    fs::write(dialogue_dir.join("D4.yaml"), yaml).unwrap();
+
+    let report = import_dialogues(&dialogue_dir, &text_dir, DialogueFormat::Yaml, &table).unwrap();
+    assert!(report.failures.is_empty());
+    assert_eq!(
+        fs::read(text_dir.join("D4.TCT")).unwrap(),
+        encode_tct(&DIALOGUE.replace("Ein Bier", "Ein Wasser")).unwrap()
+    );
+}
+
+#[test]
+fn test_run_dialogues_reads_zip_archives() {
+    let tempdir = tempfile::tempdir().unwrap();
+    let archive_filename = tempdir.path().join("jonathan.zip");
+    let dialogue_dir = tempdir.path().join("DIALOGUES");
+
+    let mut zip = zip::ZipWriter::new(fs::File::create(&archive_filename).unwrap());
+    zip.start_file("JONATHAN/text/D4.TCT", Default::default())
+        .unwrap();
+    zip.write_all(&encode_tct(DIALOGUE).unwrap()).unwrap();
+    zip.finish().unwrap();
+
+    let table = CodecTable::default();
+    let layout = DirectoryLayout::default();
+    run_dialogues(
+        &archive_filename,
+        &layout,
+        &dialogue_dir,
+        DialogueFormat::Yaml,
+        false,
+        &table,
+    )
+    .unwrap();
+    assert!(dialogue_dir.join("D4.yaml").is_file());
+
+    assert!(run_dialogues(
+        &archive_filename,
+        &layout,
+        &dialogue_dir,
+        DialogueFormat::Yaml,
+        true,
+        &table,
+    )
+    .is_err());
+}
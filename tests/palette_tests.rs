@@ -0,0 +1,183 @@
+use jonathan_converter::{
+    decode_pcx, encode_pcx, encode_png, extract_palettes, format_act_palette, remap_image,
+    run_palettes, run_remap, DirectoryLayout, JonathanImage, PaletteDepth, PaletteMode, RemapMode,
+    TransparentColor,
+};
+use std::{fs, io::Write};
+
+fn make_image(color: [u8; 3]) -> JonathanImage {
+    JonathanImage {
+        width: 2,
+        height: 1,
+        indices: vec![0, 1],
+        palette: [color; 256],
+        signature: None,
+        palette_depth: PaletteDepth::EightBit,
+    }
+}
+
+#[test]
+fn test_extract_palettes() {
+    let tempdir = tempfile::tempdir().unwrap();
+    let gfx_dir = tempdir.path().join("GRAFIK");
+    fs::create_dir_all(&gfx_dir).unwrap();
+
+    for (name, color) in [
+        ("ORT1.PCX", [100, 150, 200]),
+        ("ORT2.PCX", [200, 150, 100]),
+        ("ORT3.PCX", [100, 150, 200]),
+    ] {
+        fs::write(gfx_dir.join(name), encode_pcx(&make_image(color)).unwrap()).unwrap();
+    }
+    fs::write(gfx_dir.join("BROKEN.PCX"), [0x0a]).unwrap();
+
+    let catalog = extract_palettes(&gfx_dir, PaletteMode::Auto).unwrap();
+
+    assert_eq!(catalog.failures.len(), 1);
+    assert_eq!(catalog.entries.len(), 2);
+    assert_eq!(catalog.entries[0].name, "ORT1");
+    assert_eq!(
+        catalog.entries[0].images,
+        [gfx_dir.join("ORT1.PCX"), gfx_dir.join("ORT3.PCX")]
+    );
+    assert_eq!(catalog.entries[1].colors, [[200, 150, 100]; 256]);
+
+    let output_dir = tempdir.path().join("PALETTES");
+    catalog.write(&output_dir).unwrap();
+
+    let jasc = fs::read_to_string(output_dir.join("ORT2.pal")).unwrap();
+    assert!(jasc.starts_with("JASC-PAL\r\n0100\r\n256\r\n200 150 100\r\n"));
+    assert!(output_dir.join("ORT1.gpl").is_file());
+    assert_eq!(fs::read(output_dir.join("ORT1.act")).unwrap().len(), 772);
+
+    let report = fs::read_to_string(output_dir.join("PALETTES.TXT")).unwrap();
+    assert!(report.contains("ORT1 (256 colors, 2 images):\n    ORT1.PCX\n    ORT3.PCX\n"));
+}
+
+#[test]
+fn test_run_palettes_reads_zip_archives() {
+    let tempdir = tempfile::tempdir().unwrap();
+    let archive_filename = tempdir.path().join("jonathan.zip");
+
+    let mut zip = zip::ZipWriter::new(fs::File::create(&archive_filename).unwrap());
+    zip.start_file("JONATHAN/grafik/ORT1.PCX", Default::default())
+        .unwrap();
+    zip.write_all(&encode_pcx(&make_image([100, 150, 200])).unwrap())
+        .unwrap();
+    zip.finish().unwrap();
+
+    let output_dir = tempdir.path().join("PALETTES");
+    run_palettes(
+        &archive_filename,
+        &DirectoryLayout::default(),
+        &output_dir,
+        PaletteMode::Auto,
+    )
+    .unwrap();
+
+    let jasc = fs::read_to_string(output_dir.join("ORT1.pal")).unwrap();
+    assert!(jasc.starts_with("JASC-PAL\r\n0100\r\n256\r\n100 150 200\r\n"));
+}
+
+#[test]
+fn test_format_act_palette() {
+    let act = format_act_palette(&[[1, 2, 3], [4, 5, 6]]);
+    assert_eq!(act[..6], [1, 2, 3, 4, 5, 6]);
+    assert_eq!(act[768..], [0, 2, 0xff, 0xff]);
+}
+
+#[test]
+fn test_remap_image() {
+    let mut reference = make_image([0, 0, 0]);
+    reference.palette[1] = [255, 0, 0];
+    reference.palette[2] = [0, 0, 250];
+
+    let mut image = make_image([100, 150, 200]);
+    image.indices = vec![0, 1, 2, 0];
+    image.width = 4;
+    image.palette[0] = [0, 0, 0];
+    image.palette[1] = [0, 0, 250];
+    image.palette[2] = [240, 10, 10];
+
+    let remapped = remap_image(&image, &reference, RemapMode::Nearest, None).unwrap();
+    assert_eq!(remapped.indices, [0, 2, 1, 0]);
+    assert_eq!(remapped.palette, reference.palette);
+
+    let err = remap_image(&image, &reference, RemapMode::Exact, None).unwrap_err();
+    assert!(err.to_string().contains("palette index 2"), "{}", err);
+
+    image.indices = vec![0, 1, 1, 0];
+    let remapped = remap_image(&image, &reference, RemapMode::Exact, None).unwrap();
+    assert_eq!(remapped.indices, [0, 2, 2, 0]);
+}
+
+#[test]
+fn test_remap_image_keeps_transparent_index() {
+    let mut reference = make_image([0, 0, 0]);
+    reference.palette[1] = [255, 0, 0];
+
+    // Index 3 is the color key, index 1 is an opaque black that is closest to reference index 0.
+    let mut image = make_image([100, 150, 200]);
+    image.indices = vec![3, 1, 2, 3];
+    image.width = 4;
+    image.palette[1] = [0, 0, 0];
+    image.palette[2] = [240, 10, 10];
+    image.palette[3] = [255, 0, 255];
+
+    let remapped = remap_image(&image, &reference, RemapMode::Nearest, Some(3)).unwrap();
+    assert_eq!(remapped.indices, [3, 0, 1, 3]);
+
+    // Reference index 0 is reserved for the color key, the black pixel takes the next black.
+    image.indices = vec![0, 1, 2, 0];
+    let remapped = remap_image(&image, &reference, RemapMode::Nearest, Some(0)).unwrap();
+    assert_eq!(remapped.indices, [0, 2, 1, 0]);
+    assert_eq!(remapped.palette[2], [0, 0, 0]);
+}
+
+#[test]
+fn test_remap_mixes_pcx_and_png_files() {
+    let tempdir = tempfile::tempdir().unwrap();
+    let pcx_filename = tempdir.path().join("G1_1.PCX");
+    let png_filename = tempdir.path().join("ORT1.PNG");
+
+    // The PCX file has a 6 bit VGA palette, the PNG file the same colors as 8 bit values.
+    let mut pcx_image = make_image([0, 0, 0]);
+    pcx_image.palette[1] = [63, 0, 0];
+    pcx_image.palette[2] = [0, 63, 0];
+    pcx_image.indices = vec![1, 2];
+    fs::write(&pcx_filename, encode_pcx(&pcx_image).unwrap()).unwrap();
+
+    let mut png_image = make_image([0, 0, 255]);
+    png_image.palette[5] = [255, 0, 0];
+    png_image.palette[6] = [0, 255, 0];
+    png_image.indices = vec![6, 5];
+    fs::write(&png_filename, encode_png(&png_image).unwrap()).unwrap();
+
+    let output_filename = tempdir.path().join("remapped.pcx");
+    run_remap(
+        &pcx_filename,
+        &png_filename,
+        &output_filename,
+        RemapMode::Exact,
+        TransparentColor::None,
+        PaletteMode::Auto,
+    )
+    .unwrap();
+    let remapped = decode_pcx(&fs::read(&output_filename).unwrap()).unwrap();
+    assert_eq!(remapped.indices, [5, 6]);
+    assert_eq!(remapped.palette[5], [255, 0, 0]);
+
+    // Remapped onto the PCX file, the palette is written with 6 bit values again.
+    run_remap(
+        &png_filename,
+        &pcx_filename,
+        &output_filename,
+        RemapMode::Exact,
+        TransparentColor::None,
+        PaletteMode::Auto,
+    )
+    .unwrap();
+    let remapped = decode_pcx(&fs::read(&output_filename).unwrap()).unwrap();
+    assert_eq!(remapped.indices, [2, 1]);
+    assert_eq!(remapped.palette, pcx_image.palette);
+}
@@ -0,0 +1,206 @@
+use jonathan_converter::{
+    decode_pcx, decode_pcx_with_mode, detect_palette_depth, encode_pcx, normalize_palette,
+    DecodeMode, JonathanImage, PaletteDepth, PaletteMode, PcxPixels,
+};
+
+fn test_palette() -> [[u8; 3]; 256] {
+    let mut palette = [[0u8; 3]; 256];
+    for (i, entry) in palette.iter_mut().enumerate() {
+        *entry = [i as u8, 255 - i as u8, (i * 7) as u8];
+    }
+    palette
+}
+
+// Builds a 256 color PCX file with the obfuscated signature the game uses.
+fn make_game_pcx(width: u16, height: u16, indices: &[u8], palette: &[[u8; 3]; 256]) -> Vec<u8> {
+    let bytes_per_line = (width + 1) & !1;
+
+    let mut data = vec![0u8; 128];
+    data[..4].copy_from_slice(&[0x12, 0x34, 0x56, 0x78]);
+    data[8..10].copy_from_slice(&(width - 1).to_le_bytes());
+    data[10..12].copy_from_slice(&(height - 1).to_le_bytes());
+    data[65] = 1;
+    data[66..68].copy_from_slice(&bytes_per_line.to_le_bytes());
+    data[68] = 1;
+
+    for row in indices.chunks(width as usize) {
+        for x in 0..bytes_per_line as usize {
+            data.extend_from_slice(&[0xc1, row.get(x).copied().unwrap_or(0)]);
+        }
+    }
+
+    data.push(0x0c);
+    data.extend(palette.iter().flatten());
+    data
+}
+
+#[test]
+fn test_decode_pcx() {
+    let indices = [0, 1, 2, 200, 201, 255];
+    let palette = test_palette();
+    let image = decode_pcx(&make_game_pcx(3, 2, &indices, &palette)).unwrap();
+
+    assert_eq!(image.width, 3);
+    assert_eq!(image.height, 2);
+    assert_eq!(image.indices, indices);
+    assert_eq!(image.palette, palette);
+}
+
+#[test]
+fn test_decode_pcx_rejects_short_data() {
+    assert!(decode_pcx(&[0x0a, 0x05]).is_err());
+}
+
+#[test]
+fn test_encode_pcx_roundtrip() {
+    let image = JonathanImage {
+        width: 101,
+        height: 3,
+        indices: (0..303)
+            .map(|i| if i < 150 { 7 } else { i as u8 })
+            .collect(),
+        palette: test_palette(),
+        signature: Some([0x12, 0x34, 0x56, 0x78]),
+        palette_depth: PaletteDepth::EightBit,
+    };
+
+    let pcx_data = encode_pcx(&image).unwrap();
+
+    assert_eq!(pcx_data[..4], [0x12, 0x34, 0x56, 0x78]);
+    assert_eq!(decode_pcx(&pcx_data).unwrap(), image);
+}
+
+#[test]
+fn test_decode_truncated_pcx() {
+    let indices: Vec<u8> = (0..12).collect();
+    let mut data = make_game_pcx(3, 4, &indices, &test_palette());
+    // Keeps the header and the first row, the palette is lost as well.
+    data.truncate(128 + 4 * 2 + 1);
+
+    assert!(decode_pcx(&data).is_err());
+
+    let decoded = decode_pcx_with_mode(&data, DecodeMode::Lenient { fill_index: 9 }).unwrap();
+    assert_eq!(decoded.warnings.len(), 2);
+
+    let image = decoded.image.into_jonathan_image().unwrap();
+    assert_eq!(image.indices, [0, 1, 2, 9, 9, 9, 9, 9, 9, 9, 9, 9]);
+    assert_eq!(image.palette[200], [200, 200, 200]);
+}
+
+#[test]
+fn test_decode_heavily_truncated_pcx() {
+    let indices = vec![7u8; 320 * 200];
+    let data = make_game_pcx(320, 200, &indices, &test_palette());
+
+    for length in [128 + 2000, 128] {
+        let truncated = &data[..length];
+        assert!(decode_pcx(truncated).is_err());
+
+        let decoded =
+            decode_pcx_with_mode(truncated, DecodeMode::Lenient { fill_index: 9 }).unwrap();
+        let image = decoded.image.into_jonathan_image().unwrap();
+        assert_eq!((image.width, image.height), (320, 200));
+        // Every pair of bytes encodes a single pixel, only complete rows are kept.
+        let decoded_rows = (length - 128) / 2 / 320;
+        assert!(image.indices[..decoded_rows * 320].iter().all(|&i| i == 7));
+        assert!(image.indices[decoded_rows * 320..].iter().all(|&i| i == 9));
+    }
+}
+
+#[test]
+fn test_decode_pcx_rejects_oversized_header() {
+    let mut data = make_game_pcx(2, 2, &[0, 1, 2, 3], &test_palette());
+    data[8..12].copy_from_slice(&[0xff; 4]);
+    data[65] = 4;
+    data[66..68].copy_from_slice(&[0xff; 2]);
+
+    assert!(decode_pcx_with_mode(&data, DecodeMode::Lenient { fill_index: 0 }).is_err());
+}
+
+// Builds an uncompressed regular PCX file from raw scanlines.
+fn make_regular_pcx(
+    bits_per_pixel: u8,
+    planes: u8,
+    width: u16,
+    height: u16,
+    bytes_per_line: u16,
+    scanlines: &[u8],
+) -> Vec<u8> {
+    let mut data = vec![0u8; 128];
+    data[..4].copy_from_slice(&[0x0a, 0x02, 0x00, bits_per_pixel]);
+    data[8..10].copy_from_slice(&(width - 1).to_le_bytes());
+    data[10..12].copy_from_slice(&(height - 1).to_le_bytes());
+    for i in 0..16 {
+        data[16 + i * 3..16 + i * 3 + 3].copy_from_slice(&[i as u8 * 16; 3]);
+    }
+    data[65] = planes;
+    data[66..68].copy_from_slice(&bytes_per_line.to_le_bytes());
+    data.extend_from_slice(scanlines);
+    data
+}
+
+#[test]
+fn test_decode_planar_ega_pcx() {
+    // One row of 8 pixels in 4 bit planes, pixel x has the index x * 2 + 1.
+    let scanlines = [
+        0b1111_1111,
+        0,
+        0b0101_0101,
+        0,
+        0b0011_0011,
+        0,
+        0b0000_1111,
+        0,
+    ];
+    let decoded = decode_pcx_with_mode(
+        &make_regular_pcx(1, 4, 8, 1, 2, &scanlines),
+        DecodeMode::Strict,
+    )
+    .unwrap();
+
+    assert_eq!(
+        decoded.image.pixels,
+        PcxPixels::Indexed {
+            bits_per_pixel: 4,
+            indices: vec![1, 3, 5, 7, 9, 11, 13, 15],
+            palette: (0..16).map(|i| [i * 16; 3]).collect(),
+        }
+    );
+}
+
+#[test]
+fn test_decode_true_color_pcx() {
+    let scanlines = [1, 2, 0, 3, 4, 0, 5, 6, 0];
+    let decoded = decode_pcx_with_mode(
+        &make_regular_pcx(8, 3, 2, 1, 3, &scanlines),
+        DecodeMode::Strict,
+    )
+    .unwrap();
+
+    assert_eq!(decoded.image.pixels, PcxPixels::Rgb(vec![1, 3, 5, 2, 4, 6]));
+    assert!(decoded.image.into_jonathan_image().is_err());
+}
+
+#[test]
+fn test_normalize_palette() {
+    let mut vga_palette = [[0, 0, 0], [63, 32, 1]];
+    assert_eq!(
+        normalize_palette(&mut vga_palette, PaletteMode::Auto),
+        PaletteDepth::SixBit
+    );
+    assert_eq!(vga_palette, [[0, 0, 0], [255, 130, 4]]);
+
+    let mut palette = [[0, 0, 0], [63, 32, 1]];
+    assert_eq!(
+        normalize_palette(&mut palette, PaletteMode::EightBit),
+        PaletteDepth::EightBit
+    );
+    assert_eq!(palette, [[0, 0, 0], [63, 32, 1]]);
+
+    let mut palette = [[0, 0, 0], [64, 32, 1]];
+    assert_eq!(detect_palette_depth(&palette), PaletteDepth::EightBit);
+    assert_eq!(
+        normalize_palette(&mut palette, PaletteMode::Auto),
+        PaletteDepth::EightBit
+    );
+}
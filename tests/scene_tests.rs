@@ -0,0 +1,98 @@
+use jonathan_converter::{
+    composite_scene, encode_pcx, encode_png, load_image, JonathanImage, Overlay, PaletteDepth,
+    TransparentColor,
+};
+use std::fs;
+
+fn make_image(width: u32, height: u32, indices: Vec<u8>, colors: &[[u8; 3]]) -> JonathanImage {
+    let mut palette = [[0u8; 3]; 256];
+    palette[..colors.len()].copy_from_slice(colors);
+    JonathanImage {
+        width,
+        height,
+        indices,
+        palette,
+        signature: None,
+        palette_depth: PaletteDepth::EightBit,
+    }
+}
+
+#[test]
+fn test_composite_scene() {
+    let background = make_image(3, 2, vec![0; 6], &[[10, 10, 10]]);
+    // The sprite has its own palette, index 0 is the color key in all corners.
+    let sprite = make_image(2, 2, vec![0, 0, 0, 1], &[[0, 0, 0], [200, 0, 0]]);
+    let overlays = [
+        Overlay {
+            image: sprite.clone(),
+            x: 0,
+            y: 0,
+        },
+        Overlay {
+            image: sprite,
+            x: 1,
+            y: 1,
+        },
+    ];
+
+    let mut warnings = Vec::new();
+    let scene = composite_scene(
+        &background,
+        &overlays,
+        TransparentColor::AutoDetect,
+        &mut warnings,
+    );
+
+    assert!(warnings.is_empty());
+    assert_eq!((scene.width, scene.height), (3, 2));
+    assert_eq!(
+        scene.pixels,
+        [
+            [10, 10, 10],
+            [10, 10, 10],
+            [10, 10, 10],
+            [10, 10, 10],
+            [200, 0, 0],
+            [10, 10, 10]
+        ]
+        .concat()
+    );
+
+    let scene = composite_scene(
+        &background,
+        &overlays[..1],
+        TransparentColor::None,
+        &mut warnings,
+    );
+    assert_eq!(scene.pixels[..3], [0, 0, 0]);
+}
+
+#[test]
+fn test_composite_scene_with_six_bit_pcx_background() {
+    let tempdir = tempfile::tempdir().unwrap();
+    let background_filename = tempdir.path().join("ORT1.PCX");
+    let sprite_filename = tempdir.path().join("G1_1.PNG");
+
+    // The background has a 6 bit VGA palette, the sprite was converted to 8 bit color values.
+    let background = make_image(2, 1, vec![0, 1], &[[63, 0, 0], [0, 32, 63]]);
+    fs::write(&background_filename, encode_pcx(&background).unwrap()).unwrap();
+    let sprite = make_image(1, 1, vec![1], &[[0, 0, 0], [0, 255, 0]]);
+    fs::write(&sprite_filename, encode_png(&sprite).unwrap()).unwrap();
+
+    let background = load_image(&background_filename).unwrap();
+    assert_eq!(background.palette_depth, PaletteDepth::SixBit);
+    let overlays = [Overlay {
+        image: load_image(&sprite_filename).unwrap(),
+        x: 1,
+        y: 0,
+    }];
+
+    let mut warnings = Vec::new();
+    let scene = composite_scene(
+        &background,
+        &overlays,
+        TransparentColor::None,
+        &mut warnings,
+    );
+    assert_eq!(scene.pixels, [255, 0, 0, 0, 255, 0]);
+}
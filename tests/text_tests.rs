@@ -0,0 +1,133 @@
+use jonathan_converter::{
+    decode_tct, decode_tct_with_mode, decode_tct_with_table, detect_encoding, encode_tct,
+    encode_tct_with_mode, encode_tct_with_table, CodecTable, TextEncoding, UnmappedByte,
+    UnmappedBytes,
+};
+
+const GRUESSE: &[u8] = &[81, 124, 139, 235, 111, 10, 75, 152, 10];
+
+#[test]
+fn test_decode_tct() {
+    assert_eq!(decode_tct(GRUESSE).unwrap(), "Grüße\nAÄ\n");
+    assert_eq!(
+        decode_tct(&[77, 107, 112, 140, 10, 143]).unwrap(),
+        "Café\nà"
+    );
+    assert!(decode_tct(&[81, 5]).is_err());
+    // The bytes that would be decoded as line feed and carriage return are unmapped.
+    assert!(decode_tct(&[82, 20, 82]).is_err());
+    assert!(decode_tct(&[82, 23, 82]).is_err());
+}
+
+#[test]
+fn test_encode_tct() {
+    assert_eq!(encode_tct("Grüße\nAÄ\n").unwrap(), GRUESSE);
+    assert_eq!(encode_tct("Côte").unwrap(), [77, 183, 126, 111]);
+
+    let err = encode_tct("Hallo\nWelt €").unwrap_err().to_string();
+    assert!(err.contains("line 2, column 6"), "{}", err);
+}
+
+#[test]
+fn test_decode_tct_with_unmapped_bytes() {
+    // "A", the unmapped byte 200, "\x{" as literal text and the unmapped byte 5.
+    let data = [75, 200, 102, 130, 133, 10, 5];
+
+    assert!(decode_tct_with_mode(&data, UnmappedBytes::Reject).is_err());
+
+    let decoded = decode_tct_with_mode(&data, UnmappedBytes::Escape).unwrap();
+    assert_eq!(decoded.text, "A\\x{C8}\\x{66}x{\n\\x{05}");
+    assert_eq!(
+        decoded.unmapped,
+        [
+            UnmappedByte {
+                byte: 200,
+                offset: 1,
+                line: 1,
+                column: 2
+            },
+            UnmappedByte {
+                byte: 5,
+                offset: 6,
+                line: 2,
+                column: 1
+            }
+        ]
+    );
+    assert_eq!(
+        encode_tct_with_mode(&decoded.text, UnmappedBytes::Escape).unwrap(),
+        data
+    );
+
+    let decoded = decode_tct_with_mode(&data, UnmappedBytes::PrivateUse).unwrap();
+    assert_eq!(decoded.text, "A\u{e0c8}\\x{\n\u{e005}");
+    assert_eq!(
+        encode_tct_with_mode(&decoded.text, UnmappedBytes::PrivateUse).unwrap(),
+        data
+    );
+    assert!(encode_tct(&decoded.text).is_err());
+}
+
+#[test]
+fn test_codec_table() {
+    let table = CodecTable::parse(
+        "# A French release.\n\
+         0x0A U+000A\n\
+         11-136 U+0001\n\
+         140 é\n\
+         143 U+00E0\n\
+         150 a\n",
+    )
+    .unwrap();
+
+    let data = [77, 107, 112, 140, 10, 143, 150];
+    let decoded = decode_tct_with_table(&data, &table, UnmappedBytes::Reject).unwrap();
+    assert_eq!(decoded.text, "Café\nàa");
+    assert_eq!(
+        encode_tct_with_table(&decoded.text, &table, UnmappedBytes::Reject).unwrap(),
+        [77, 107, 112, 140, 10, 143, 107]
+    );
+
+    assert_eq!(
+        decode_tct_with_table(GRUESSE, CodecTable::german(), UnmappedBytes::Reject)
+            .unwrap()
+            .text,
+        decode_tct(GRUESSE).unwrap()
+    );
+
+    assert!(CodecTable::parse("10 U+000A U+000B").is_err());
+    assert!(CodecTable::parse("300 a").is_err());
+    assert!(CodecTable::parse("20-10 a").is_err());
+    assert!(CodecTable::parse("10 a\n10 b").is_err());
+}
+
+#[test]
+fn test_detect_encoding() {
+    let text = "Jonathan öffnet die Tür und geht hinaus.\nDraußen ist es kalt und dunkel.\n";
+    let data = encode_tct(text).unwrap();
+
+    let (encoding, table) = detect_encoding(&data, &[CodecTable::german()]);
+    assert_eq!(
+        encoding,
+        TextEncoding {
+            table: "german".to_owned(),
+            shift: 0
+        }
+    );
+    assert_eq!(&table, CodecTable::german());
+
+    let shifted: Vec<u8> = data.iter().map(|byte| byte - 3).collect();
+    let (encoding, table) = detect_encoding(&shifted, &[CodecTable::german()]);
+    assert_eq!(encoding.shift, -3);
+    assert_eq!(encoding.to_string(), "'german' shifted by -3");
+    assert_eq!(
+        decode_tct_with_table(&shifted, &table, UnmappedBytes::Reject)
+            .unwrap()
+            .text,
+        text
+    );
+    assert_eq!(
+        encode_tct_with_table(text, &table, UnmappedBytes::Reject).unwrap(),
+        shifted
+    );
+}